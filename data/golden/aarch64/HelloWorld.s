@@ -0,0 +1,197 @@
+// 30000 cells of 1 bytes, the pointer in x19 and the count of buffered
+// output in x20; links without libc: as -o program.o program.s && ld -o program program.o
+
+    .bss
+    .balign 64
+tape:
+    .zero 30000 * 1
+buffer:
+    .zero 4096
+input:
+    .zero 1
+
+    .text
+    .globl _start
+_start:
+    adrp x19, tape
+    add x19, x19, :lo12:tape
+    mov x20, #0
+
+    add x19, x19, #1
+    ldrb w0, [x19]
+    add w0, w0, #8
+    strb w0, [x19]
+    ldrb w0, [x19]
+    cbz w0, 1f
+    sub x2, x19, #1
+    ldrb w1, [x2]
+    mov w3, #9
+    madd w1, w0, w3, w1
+    strb w1, [x2]
+1:
+    strb wzr, [x19]
+    sub x19, x19, #1
+    ldrb w0, [x19]
+    bl putchar
+    add x19, x19, #1
+    ldrb w0, [x19]
+    add w0, w0, #4
+    strb w0, [x19]
+    ldrb w0, [x19]
+    cbz w0, 1f
+    sub x2, x19, #1
+    ldrb w1, [x2]
+    mov w3, #7
+    madd w1, w0, w3, w1
+    strb w1, [x2]
+1:
+    strb wzr, [x19]
+    sub x19, x19, #1
+    ldrb w0, [x19]
+    add w0, w0, #1
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    ldrb w0, [x19]
+    add w0, w0, #7
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    ldrb w0, [x19]
+    bl putchar
+    ldrb w0, [x19]
+    add w0, w0, #3
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    add x19, x19, #2
+    ldrb w0, [x19]
+    add w0, w0, #6
+    strb w0, [x19]
+    ldrb w0, [x19]
+    cbz w0, 1f
+    sub x2, x19, #1
+    ldrb w1, [x2]
+    mov w3, #7
+    madd w1, w0, w3, w1
+    strb w1, [x2]
+1:
+    strb wzr, [x19]
+    sub x19, x19, #1
+    ldrb w0, [x19]
+    add w0, w0, #2
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    ldrb w0, [x19]
+    sub w0, w0, #12
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    add x19, x19, #1
+    ldrb w0, [x19]
+    add w0, w0, #6
+    strb w0, [x19]
+    ldrb w0, [x19]
+    cbz w0, 1f
+    sub x2, x19, #1
+    ldrb w1, [x2]
+    mov w3, #9
+    madd w1, w0, w3, w1
+    strb w1, [x2]
+1:
+    strb wzr, [x19]
+    sub x19, x19, #1
+    ldrb w0, [x19]
+    add w0, w0, #1
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    sub x19, x19, #1
+    ldrb w0, [x19]
+    bl putchar
+    ldrb w0, [x19]
+    add w0, w0, #3
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    ldrb w0, [x19]
+    sub w0, w0, #6
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    ldrb w0, [x19]
+    sub w0, w0, #8
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+    add x19, x19, #3
+    ldrb w0, [x19]
+    add w0, w0, #4
+    strb w0, [x19]
+    ldrb w0, [x19]
+    cbz w0, 1f
+    sub x2, x19, #1
+    ldrb w1, [x2]
+    mov w3, #8
+    madd w1, w0, w3, w1
+    strb w1, [x2]
+1:
+    strb wzr, [x19]
+    sub x19, x19, #1
+    ldrb w0, [x19]
+    add w0, w0, #1
+    strb w0, [x19]
+    ldrb w0, [x19]
+    bl putchar
+
+    bl flush
+    mov x0, #0
+    mov x8, #93
+    svc #0
+
+// writes the buffered output to stdout
+flush:
+    adrp x1, buffer
+    add x1, x1, :lo12:buffer
+    mov x2, x20
+1:  cbz x2, 2f
+    mov x0, #1
+    mov x8, #64
+    svc #0
+    cmp x0, #0
+    b.le 2f
+    add x1, x1, x0
+    sub x2, x2, x0
+    b 1b
+2:  mov x20, #0
+    ret
+
+// buffers the byte in w0
+putchar:
+    adrp x1, buffer
+    add x1, x1, :lo12:buffer
+    strb w0, [x1, x20]
+    add x20, x20, #1
+    cmp x20, #4095
+    b.hi flush
+    ret
+
+// flushes the output, then reads a byte from stdin into w0, or -1 at the end of input
+getchar:
+    str x30, [sp, #-16]!
+    bl flush
+    ldr x30, [sp], #16
+    mov x0, #0
+    adrp x1, input
+    add x1, x1, :lo12:input
+    mov x2, #1
+    mov x8, #63
+    svc #0
+    cmp x0, #0
+    b.le 1f
+    adrp x1, input
+    ldrb w0, [x1, :lo12:input]
+    ret
+1:  mov w0, #-1
+    ret
@@ -0,0 +1,193 @@
+#![cfg_attr(feature = "nightly", feature(proc_macro_span))]
+
+use std::{ops::Range, str::FromStr};
+
+use bf::{
+    backends::{Config, Emitter, transpilers::rust::Transpiler},
+    core::{ir::optimizers::Optimizers, parser::Parser, tokenizer::Tokenizer},
+};
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Compiles brainfuck into a function with the signature of
+/// [`rust::function`](bf::backends::transpilers::rust::function), on a tape of 30000 byte
+/// cells. The program is written as tokens, which keeps Rust's own error at a stray
+/// bracket, or as one string literal for sources that are not Rust tokens, read as
+/// written between the quotes.
+///
+/// ```
+/// use bf_macros::bf;
+///
+/// let letter = bf! { ++++++++[>++++++++<-]>+. };
+/// let mut output = vec![];
+/// letter(&mut std::io::empty(), &mut output).unwrap();
+/// assert_eq!(output, b"A");
+/// ```
+///
+/// An unmatched bracket is a compile error at the bracket. In a string literal it covers
+/// the whole literal instead, as pointing into one needs the unstable `Literal::subspan`,
+/// which the `nightly` feature uses.
+///
+/// ```compile_fail
+/// let broken = bf_macros::bf!("+[>+<-]]");
+/// ```
+#[proc_macro]
+pub fn bf(input: TokenStream) -> TokenStream {
+    let mut tokens = input.clone().into_iter();
+    let (text, spans) = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) if string(&literal).is_some() => {
+            let text = string(&literal).unwrap_or_default();
+            let spans = spans(&literal, &text);
+            (text, spans)
+        }
+        _ => {
+            let mut program = vec![];
+            flatten(input, &mut program);
+            program.into_iter().unzip()
+        }
+    };
+
+    match expand(&text) {
+        Ok(code) => TokenStream::from_str(&code).expect("the rust emitter writes valid tokens"),
+        Err(index) => {
+            let bracket = text.chars().nth(index).unwrap_or('[');
+            let (line, column) = position(&text, index);
+            error(
+                &format!("unmatched `{bracket}` at {line}:{column} of the program"),
+                spans[index],
+            )
+        }
+    }
+}
+
+/// The Rust for `text` as a block that evaluates to its function, or the char index of
+/// the bracket that keeps it from parsing.
+fn expand(text: &str) -> Result<String, usize> {
+    let tokens = Tokenizer::tokenize(text);
+    if let Some(index) = Parser::unbalanced(&tokens) {
+        return Err(index);
+    }
+    let expressions = Optimizers::optimize(&Parser::parse(&tokens));
+
+    let config = Config {
+        function: Some("run".to_string()),
+        ..Config::default()
+    };
+    let mut code = vec![];
+    Transpiler
+        .emit(&expressions, None, &config, &mut code)
+        .expect("the default tape suits a function");
+
+    Ok(format!(
+        "{{\n{}\nrun\n}}",
+        String::from_utf8(code).expect("rust is text")
+    ))
+}
+
+/// The text between the quotes of a string or raw string literal.
+fn string(literal: &Literal) -> Option<String> {
+    let text = literal.to_string();
+    let raw = text.strip_prefix('r').unwrap_or(&text);
+    let hashes = raw.len() - raw.trim_start_matches('#').len();
+
+    raw[hashes..]
+        .strip_prefix('"')?
+        .strip_suffix(&raw[..hashes])?
+        .strip_suffix('"')
+        .map(str::to_string)
+}
+
+/// The span of every char of `text`, which is what `literal` holds between its quotes.
+fn spans(literal: &Literal, text: &str) -> Vec<Span> {
+    // the `r` and hashes of a raw string come before the quote
+    let start = literal.to_string().find('"').map_or(0, |quote| quote + 1);
+
+    text.char_indices()
+        .map(|(index, char)| subspan(literal, start + index..start + index + char.len_utf8()))
+        .collect()
+}
+
+#[cfg(feature = "nightly")]
+fn subspan(literal: &Literal, range: Range<usize>) -> Span {
+    literal.subspan(range).unwrap_or_else(|| literal.span())
+}
+
+#[cfg(not(feature = "nightly"))]
+fn subspan(literal: &Literal, _range: Range<usize>) -> Span {
+    literal.span()
+}
+
+/// The chars of `input` with their spans, with the brackets of `[...]` groups as commands
+/// and every other delimiter, identifier and literal as a comment.
+fn flatten(input: TokenStream, program: &mut Vec<(char, Span)>) {
+    for tree in input {
+        match tree {
+            TokenTree::Punct(punct) => program.push((punct.as_char(), punct.span())),
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Bracket => {
+                program.push(('[', group.span_open()));
+                flatten(group.stream(), program);
+                program.push((']', group.span_close()));
+            }
+            TokenTree::Group(group) => flatten(group.stream(), program),
+            TokenTree::Ident(_) | TokenTree::Literal(_) => {}
+        }
+    }
+}
+
+/// The one-based line and column of the char at `index` in `text`.
+fn position(text: &str, index: usize) -> (usize, usize) {
+    let before = text.chars().take(index).collect::<String>();
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .chars()
+        .rev()
+        .take_while(|char| *char != '\n')
+        .count()
+        + 1;
+    (line, column)
+}
+
+/// `compile_error!(message)` pointing at `span`.
+fn error(message: &str, span: Span) -> TokenStream {
+    let mut literal = Literal::string(message);
+    literal.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    let mut group = Group::new(Delimiter::Brace, TokenTree::Literal(literal).into());
+    group.set_span(span);
+
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(group),
+    ]
+    .into_iter()
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{expand, position};
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    #[test_case("+]", 1)]
+    #[test_case("[+", 0)]
+    #[test_case("[[]\n[]", 0)]
+    #[test_case("[]]\n[", 2)]
+    fn unbalanced(text: &str, index: usize) {
+        assert_eq!(expand(text), Err(index));
+    }
+
+    #[test]
+    fn function() {
+        let code = expand("+[>+<-].").unwrap();
+        assert!(code.starts_with("{\npub fn run("));
+        assert!(code.ends_with("\nrun\n}"));
+    }
+
+    #[test_case("+]", 1, (1, 2))]
+    #[test_case("+\n\n +]", 5, (3, 3))]
+    fn positions(text: &str, index: usize, expected: (usize, usize)) {
+        assert_eq!(position(text, index), expected);
+    }
+}
@@ -0,0 +1,49 @@
+/*
+ * The C ABI of the bf library, built as libbf.so and libbf.a.
+ *
+ * A program is compiled once into an opaque handle and run on the vm any number of
+ * times, on a tape of 30000 byte cells. Failing calls return NULL or -1 and leave a
+ * message for bf_last_error.
+ */
+
+#ifndef BF_H
+#define BF_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BfProgram BfProgram;
+
+/*
+ * Compiles the length bytes of brainfuck at text, or returns NULL when they are not
+ * UTF-8 or their brackets do not match.
+ */
+BfProgram *bf_compile(const char *text, size_t length);
+
+/*
+ * Runs program on the input_length bytes at input, with end of input reading as 0, and
+ * writes its output to output. Returns 0, or -1 when the output needs more than
+ * output_capacity bytes or the pointer leaves the tape. Either way output_length is set
+ * to the bytes written. input and output may be NULL when their length is 0.
+ */
+int bf_run_with_buffers(const BfProgram *program, const uint8_t *input, size_t input_length,
+                        uint8_t *output, size_t output_capacity, size_t *output_length);
+
+/* Frees a program from bf_compile; NULL is ignored. */
+void bf_free(BfProgram *program);
+
+/*
+ * The message of the last call that failed on this thread, or NULL before any did. It
+ * lives until the next failure on the thread.
+ */
+const char *bf_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,71 @@
+#include <stdio.h>
+#include <string.h>
+
+#include "bf.h"
+
+static int failures = 0;
+
+#define CHECK(condition)                                                               \
+    do {                                                                               \
+        if (!(condition)) {                                                            \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #condition); \
+            failures++;                                                                \
+        }                                                                              \
+    } while (0)
+
+static BfProgram *compile(const char *text) {
+    return bf_compile(text, strlen(text));
+}
+
+static void letter(void) {
+    BfProgram *program = compile("++++++++[>++++++++<-]>+.");
+    uint8_t output[4];
+    size_t length = 99;
+
+    CHECK(program != NULL);
+    // a handle runs any number of times
+    for (int run = 0; run < 2; run++) {
+        CHECK(bf_run_with_buffers(program, NULL, 0, output, sizeof output, &length) == 0);
+        CHECK(length == 1 && output[0] == 'A');
+    }
+    bf_free(program);
+}
+
+static void echo(void) {
+    BfProgram *program = compile(",[.,]");
+    const uint8_t input[] = "echo";
+    uint8_t output[8];
+    size_t length = 0;
+
+    CHECK(bf_run_with_buffers(program, input, 4, output, sizeof output, &length) == 0);
+    CHECK(length == 4 && memcmp(output, "echo", 4) == 0);
+
+    CHECK(bf_run_with_buffers(program, input, 4, output, 2, &length) == -1);
+    CHECK(length == 2 && memcmp(output, "ec", 2) == 0);
+    CHECK(strstr(bf_last_error(), "does not fit") != NULL);
+    bf_free(program);
+}
+
+static void errors(void) {
+    CHECK(compile("+[>+<-]]") == NULL);
+    CHECK(strcmp(bf_last_error(), "unmatched `]` at char 7 of the program") == 0);
+    CHECK(compile("[+") == NULL);
+    CHECK(bf_compile(NULL, 1) == NULL);
+
+    BfProgram *program = compile("-[<-]");
+    size_t length = 0;
+    CHECK(bf_run_with_buffers(program, NULL, 0, NULL, 0, &length) == -1);
+    CHECK(strcmp(bf_last_error(), "pointer moved outside the tape") == 0);
+    bf_free(program);
+
+    CHECK(bf_run_with_buffers(NULL, NULL, 0, NULL, 0, &length) == -1);
+    bf_free(NULL);
+}
+
+int main(void) {
+    CHECK(bf_last_error() == NULL);
+    letter();
+    echo();
+    errors();
+    return failures != 0;
+}
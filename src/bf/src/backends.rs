@@ -1,2 +1,524 @@
+use std::{
+    io::{self, Read, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+use crate::{
+    backends::{
+        transpilers::{aarch64, c, elf, javascript, llvm, python, rust, wasm, x86_64},
+        vm::EngineKind,
+    },
+    core::{ir::Expression, source_map::Span},
+};
+
 pub mod transpilers;
 pub mod vm;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CellWidth {
+    U8,
+    U16,
+    U32,
+}
+
+impl CellWidth {
+    pub const ALL: &'static [CellWidth] = &[CellWidth::U8, CellWidth::U16, CellWidth::U32];
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            CellWidth::U8 => 8,
+            CellWidth::U16 => 16,
+            CellWidth::U32 => 32,
+        }
+    }
+}
+
+impl FromStr for CellWidth {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        CellWidth::ALL
+            .iter()
+            .find(|width| width.bits().to_string() == text)
+            .copied()
+            .ok_or_else(|| format!("invalid cell width `{text}`, expected 8, 16 or 32"))
+    }
+}
+
+impl std::fmt::Display for CellWidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.bits())
+    }
+}
+
+/// What `,` stores once the input is exhausted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Eof {
+    Zero,
+    Unchanged,
+    MinusOne,
+}
+
+impl Eof {
+    pub const ALL: &'static [Eof] = &[Eof::Zero, Eof::Unchanged, Eof::MinusOne];
+}
+
+impl FromStr for Eof {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Eof::ALL
+            .iter()
+            .find(|eof| eof.to_string() == text)
+            .copied()
+            .ok_or_else(|| format!("invalid eof `{text}`, expected zero, unchanged or minus-one"))
+    }
+}
+
+impl std::fmt::Display for Eof {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Eof::Zero => write!(f, "zero"),
+            Eof::Unchanged => write!(f, "unchanged"),
+            Eof::MinusOne => write!(f, "minus-one"),
+        }
+    }
+}
+
+/// What emitted code does when the pointer leaves the tape; executors ignore it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Tape {
+    /// Nothing, which is undefined behaviour in C and a panic in Rust.
+    Unchecked,
+    /// Stops with an error naming the cell.
+    Checked,
+    /// Grows the tape to the right and stops with an error on the left.
+    Growing,
+}
+
+impl Tape {
+    pub const ALL: &'static [Tape] = &[Tape::Unchecked, Tape::Checked, Tape::Growing];
+}
+
+impl FromStr for Tape {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Tape::ALL
+            .iter()
+            .find(|tape| tape.to_string() == text)
+            .copied()
+            .ok_or_else(|| format!("invalid tape `{text}`, expected unchecked, checked or growing"))
+    }
+}
+
+impl std::fmt::Display for Tape {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Tape::Unchecked => write!(f, "unchecked"),
+            Tape::Checked => write!(f, "checked"),
+            Tape::Growing => write!(f, "growing"),
+        }
+    }
+}
+
+/// How emitters lay out the code they write; executors ignore it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Style {
+    /// One runtime macro per expression.
+    Macros,
+    /// Plain statements on a cell pointer, like `p[2] += p[0] * 3;`.
+    Plain,
+}
+
+impl Style {
+    pub const ALL: &'static [Style] = &[Style::Macros, Style::Plain];
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Style::ALL
+            .iter()
+            .find(|style| style.to_string() == text)
+            .copied()
+            .ok_or_else(|| format!("invalid style `{text}`, expected macros or plain"))
+    }
+}
+
+impl std::fmt::Display for Style {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Style::Macros => write!(f, "macros"),
+            Style::Plain => write!(f, "plain"),
+        }
+    }
+}
+
+/// How the WebAssembly emitters write a module; the others ignore it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// The binary `.wasm` module hosts load.
+    Wasm,
+    /// The `.wat` text form of the same module, for reading it.
+    Wat,
+}
+
+impl Format {
+    pub const ALL: &'static [Format] = &[Format::Wasm, Format::Wat];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Format::ALL
+            .iter()
+            .find(|format| format.to_string() == text)
+            .copied()
+            .ok_or_else(|| format!("invalid format `{text}`, expected wasm or wat"))
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Format::Wasm => write!(f, "wasm"),
+            Format::Wat => write!(f, "wat"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Config {
+    pub cell_width: CellWidth,
+    pub tape_size: usize,
+    pub tape: Tape,
+    pub eof: Eof,
+    pub style: Style,
+    /// Puts the source of every block above it in [`Style::Plain`] code.
+    pub comments: bool,
+    /// Points plain C at the source with `#line` directives.
+    pub line_directives: bool,
+    /// Emits into this template instead of the emitter's own runtime for the [`Style`].
+    pub runtime: Option<String>,
+    pub format: Format,
+    /// Emits Rust as a `pub fn` of this name, reading and writing the streams it is given,
+    /// instead of a program.
+    pub function: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            cell_width: CellWidth::U8,
+            tape_size: 30_000,
+            tape: Tape::Unchecked,
+            eof: Eof::Zero,
+            style: Style::Macros,
+            comments: false,
+            line_directives: false,
+            runtime: None,
+            format: Format::Wasm,
+            function: None,
+        }
+    }
+}
+
+/// IR constructs beyond moving, changing and looping, which every backend handles.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Feature {
+    MulVal,
+    Clear,
+    Input,
+    Output,
+}
+
+impl Feature {
+    pub const ALL: &'static [Feature] = &[
+        Feature::MulVal,
+        Feature::Clear,
+        Feature::Input,
+        Feature::Output,
+    ];
+
+    pub fn used(expressions: &[Expression]) -> Vec<Feature> {
+        let mut used = vec![];
+        Self::collect(expressions, &mut used);
+        used
+    }
+
+    fn collect(expressions: &[Expression], used: &mut Vec<Feature>) {
+        for expression in expressions {
+            let feature = match expression {
+                Expression::MulVal(..) => Feature::MulVal,
+                Expression::Clear => Feature::Clear,
+                Expression::Input => Feature::Input,
+                Expression::Output => Feature::Output,
+                Expression::Loop(body) => {
+                    Self::collect(body, used);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if !used.contains(&feature) {
+                used.push(feature);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Features {
+    pub cell_widths: &'static [CellWidth],
+    pub tape_sizes: RangeInclusive<usize>,
+    pub eofs: &'static [Eof],
+    pub expressions: &'static [Feature],
+}
+
+pub trait Backend {
+    fn name(&self) -> &'static str;
+
+    fn features(&self) -> Features;
+
+    /// Fails with `InvalidInput` when `config` or `expressions` need something the
+    /// backend does not list in its [`Features`].
+    fn check(&self, expressions: &[Expression], config: &Config) -> io::Result<()> {
+        let features = self.features();
+        let unsupported = |what: String| {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} does not support {what}", self.name()),
+            ))
+        };
+
+        if !features.cell_widths.contains(&config.cell_width) {
+            return unsupported(format!("{}-bit cells", config.cell_width));
+        }
+        if !features.tape_sizes.contains(&config.tape_size) {
+            return unsupported(format!("a tape of {} cells", config.tape_size));
+        }
+        if !features.eofs.contains(&config.eof) {
+            return unsupported(format!("the `{}` eof policy", config.eof));
+        }
+
+        match Feature::used(expressions)
+            .into_iter()
+            .find(|feature| !features.expressions.contains(feature))
+        {
+            Some(feature) => unsupported(format!("{feature:?}")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A backend that runs programs itself.
+pub trait Executor: Backend {
+    fn execute(
+        &self,
+        expressions: &[Expression],
+        config: &Config,
+        input: Box<dyn Read>,
+        output: Box<dyn Write>,
+    ) -> io::Result<()>;
+}
+
+/// The program text an emitter was given, for comments, `#line` directives and source maps.
+#[derive(Clone, Copy, Debug)]
+pub struct Source<'a> {
+    pub path: &'a str,
+    pub text: &'a str,
+}
+
+/// A line of emitted code and the span of the source it came from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Mapping {
+    pub line: usize,
+    pub span: Span,
+}
+
+/// A backend that turns programs into source for another toolchain.
+pub trait Emitter: Backend {
+    /// File extension of the code emitted with `config`, without the dot.
+    fn extension(&self, config: &Config) -> &'static str;
+
+    /// Whether the output is a program to run rather than code to build.
+    fn executable(&self) -> bool {
+        false
+    }
+
+    /// Writes the program as it goes, so large programs are never held in memory whole.
+    /// `source` is what `expressions` were parsed from; given it, the result maps lines
+    /// of the output back to it where the backend can.
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>>;
+}
+
+pub fn executors() -> Vec<Box<dyn Executor>> {
+    vec![
+        Box::new(EngineKind::Vm),
+        Box::new(EngineKind::Threaded),
+        Box::new(EngineKind::Jit),
+    ]
+}
+
+pub fn emitters() -> Vec<Box<dyn Emitter>> {
+    vec![
+        Box::new(c::Transpiler),
+        Box::new(rust::Transpiler),
+        Box::new(llvm::Transpiler),
+        Box::new(wasm::Transpiler {
+            flavour: wasm::Flavour::Imports,
+        }),
+        Box::new(wasm::Transpiler {
+            flavour: wasm::Flavour::Wasi,
+        }),
+        Box::new(x86_64::Transpiler),
+        Box::new(elf::Transpiler),
+        Box::new(aarch64::Transpiler),
+        Box::new(javascript::Transpiler {
+            flavour: javascript::Flavour::Module,
+        }),
+        Box::new(javascript::Transpiler {
+            flavour: javascript::Flavour::Node,
+        }),
+        Box::new(python::Transpiler),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Write};
+
+    use crate::backends::{
+        Backend, CellWidth, Config, Eof, Executor, Feature, Features, executors,
+        vm::{EngineKind, testing::Buffer},
+    };
+    use crate::core::pipeline::Pipeline;
+    use test_case::test_case;
+
+    /// Output that fails every write, like `/dev/full`.
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WriteZero.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct OutputOnly;
+
+    impl Backend for OutputOnly {
+        fn name(&self) -> &'static str {
+            "output-only"
+        }
+
+        fn features(&self) -> Features {
+            Features {
+                cell_widths: &[CellWidth::U8],
+                tape_sizes: 1..=100,
+                eofs: Eof::ALL,
+                expressions: &[Feature::Output],
+            }
+        }
+    }
+
+    #[test_case("+.", Config::default() => matches Err(_); "tape too long")]
+    #[test_case("+.", Config { tape_size: 100, ..Config::default() } => matches Ok(()); "supported")]
+    #[test_case(",.", Config { tape_size: 100, ..Config::default() } => matches Err(_); "input")]
+    #[test_case(
+        "+[.-]",
+        Config { cell_width: CellWidth::U16, tape_size: 100, ..Config::default() } => matches Err(_);
+        "cell width"
+    )]
+    fn check(text: &str, config: Config) -> std::io::Result<()> {
+        OutputOnly.check(&Pipeline::execute(text), &config)
+    }
+
+    #[test]
+    fn engines_store_zero_on_eof() {
+        let config = Config {
+            eof: Eof::Unchanged,
+            ..Config::default()
+        };
+
+        for executor in executors() {
+            let error = executor.check(&[], &config).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test_case(EngineKind::Vm)]
+    #[test_case(EngineKind::Threaded)]
+    #[test_case(EngineKind::Jit)]
+    fn engines_report_errors(engine: EngineKind) {
+        let run = |text: &str, output: Box<dyn Write>| {
+            let expressions = Pipeline::execute(text);
+            engine.execute(
+                &expressions,
+                &Config::default(),
+                Box::new(io::empty()),
+                output,
+            )
+        };
+
+        let error = run("+[<+]", Box::new(io::sink())).unwrap_err();
+        assert_eq!(error.to_string(), "pointer moved outside the tape");
+        let error = run("+[>+]", Box::new(io::sink())).unwrap_err();
+        assert_eq!(error.to_string(), "pointer moved outside the tape");
+        let error = run("+.", Box::new(Full)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[test_case(EngineKind::Vm)]
+    #[test_case(EngineKind::Threaded)]
+    #[test_case(EngineKind::Jit)]
+    fn engines_skip_empty_mul_val(engine: EngineKind) {
+        // the loop never runs, so its target left of the tape is never touched
+        let text = format!("{}[-<+>]{}.", "<".repeat(100), "+".repeat(33));
+        let output = Buffer::default();
+
+        engine
+            .execute(
+                &Pipeline::execute(&text),
+                &Config::default(),
+                Box::new(io::empty()),
+                Box::new(output.clone()),
+            )
+            .unwrap();
+        assert_eq!(output.bytes(), b"!");
+    }
+
+    #[test_case(EngineKind::Vm)]
+    #[test_case(EngineKind::Threaded)]
+    #[test_case(EngineKind::Jit)]
+    fn engines_run_once(engine: EngineKind) {
+        let output = Buffer::default();
+        let mut program = engine.compile(&Pipeline::execute("+."));
+        program.set_output(Box::new(output.clone()));
+        program.run().unwrap();
+        program.run().unwrap();
+
+        assert_eq!(program.memory()[program.pointer()], 1);
+        assert_eq!(output.bytes(), [1]);
+
+        let mut program = engine.compile(&Pipeline::execute("+[<+]"));
+        let first = program.run().unwrap_err();
+        let second = program.run().unwrap_err();
+
+        assert_eq!(first.to_string(), second.to_string());
+        assert_eq!(program.pointer(), 0);
+    }
+}
@@ -1,8 +1,633 @@
-use crate::core::ir::Expression;
+use std::io::{self, Write};
 
+use crate::{
+    backends::{Mapping, Source},
+    core::{
+        ir::Expression,
+        source_map::{Position, SourceMap, Span},
+    },
+};
+
+pub mod aarch64;
 pub mod c;
+pub mod elf;
+pub mod javascript;
+pub mod llvm;
+pub mod python;
 pub mod rust;
+pub mod wasm;
+pub mod x86_64;
+
+/// Longest brainfuck shown in a comment before it is cut off with `...`.
+const COMMENT_LENGTH: usize = 60;
+
+/// Writes `template` to `output`, swapping each `<NAME>` in `values` for its value and
+/// `<CODE>` for whatever `code` writes, without building the whole program in memory.
+pub(crate) fn write_template(
+    output: &mut dyn Write,
+    template: &str,
+    values: &[(&str, &str)],
+    code: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut rest = template;
+
+    while let Some(start) = rest.find('<') {
+        output.write_all(&rest.as_bytes()[..start])?;
+        rest = &rest[start + 1..];
+
+        let name = rest.split_once('>').map_or("", |(name, _)| name);
+        let value = values.iter().find(|(key, _)| *key == name);
+        match (name, value) {
+            ("CODE", _) => code(output)?,
+            (_, Some((_, value))) => output.write_all(value.as_bytes())?,
+            (_, None) => {
+                output.write_all(b"<")?;
+                continue;
+            }
+        }
+        rest = &rest[name.len() + 1..];
+    }
+
+    output.write_all(rest.as_bytes())
+}
+
+/// `template` with each `<NAME>` in `values` swapped for its value, for the parts of a
+/// runtime that are chosen by the [`Config`](crate::backends::Config).
+pub(crate) fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = vec![];
+    write_template(&mut output, template, values, &mut |_| Ok(()))
+        .expect("writing to memory cannot fail");
+
+    String::from_utf8(output)
+        .expect("templates are text")
+        .trim_end()
+        .to_string()
+}
+
+/// Fails with `InvalidInput` unless `template` holds `<CODE>` exactly once, every other
+/// placeholder is one of `placeholders` and every one of `names`, which the code refers
+/// to, appears as a word. Placeholders are two or more capitals and underscores in angle
+/// brackets, so `<stdio.h>` and `Vec<T>` are left alone.
+pub(crate) fn validate_template(
+    template: &str,
+    placeholders: &[&str],
+    names: &[&str],
+) -> io::Result<()> {
+    let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    let mut codes = 0;
+
+    let word = |char: Option<char>| char.is_some_and(|char| char.is_alphanumeric() || char == '_');
+    let missing = names.iter().find(|name| {
+        !template.match_indices(**name).any(|(start, _)| {
+            !word(template[..start].chars().next_back())
+                && !word(template[start + name.len()..].chars().next())
+        })
+    });
+    if let Some(name) = missing {
+        return invalid(format!(
+            "runtime template does not define `{name}`, which the code uses"
+        ));
+    }
+
+    for (_, rest) in template
+        .match_indices('<')
+        .map(|(start, _)| template.split_at(start + 1))
+    {
+        let Some((name, _)) = rest.split_once('>') else {
+            continue;
+        };
+        if name.len() < 2
+            || !name
+                .chars()
+                .all(|char| char.is_ascii_uppercase() || char == '_')
+        {
+            continue;
+        }
+
+        match name {
+            "CODE" => codes += 1,
+            name if placeholders.contains(&name) => {}
+            name => {
+                return invalid(format!(
+                    "runtime template has an unknown placeholder <{name}>, expected <CODE>{}",
+                    placeholders
+                        .iter()
+                        .map(|placeholder| format!(", <{placeholder}>"))
+                        .collect::<String>()
+                ));
+            }
+        }
+    }
+
+    match codes {
+        0 => invalid("runtime template is missing the <CODE> placeholder".to_string()),
+        1 => Ok(()),
+        _ => invalid("runtime template has more than one <CODE> placeholder".to_string()),
+    }
+}
+
+/// How [`write_plain`] writes the code of one language.
+pub(crate) struct Layout<'a> {
+    pub open: &'a str,
+    /// Ends every loop, or `None` where indentation does, and empty loops get `pass`.
+    pub close: Option<&'a str>,
+    /// Starts a line comment.
+    pub comment: &'a str,
+    pub statement: &'a dyn Fn(&Expression) -> String,
+    /// Puts the brainfuck each loop and each run of statements came from above it.
+    pub comments: bool,
+    /// Puts C `#line` directives wherever the source line stops counting up by one.
+    pub directives: bool,
+    /// Line of the emitted file the code starts on.
+    pub line: usize,
+}
+
+/// Writes `expressions` as statements of one or more lines, indented by four spaces per
+/// loop, with every loop opened by `layout.open` and closed by `layout.close`. Returns the
+/// source of every statement line, which needs `source`.
+pub(crate) fn write_plain(
+    output: &mut dyn Write,
+    expressions: &[Expression],
+    source: Option<&Source>,
+    layout: &Layout,
+) -> io::Result<Vec<Mapping>> {
+    let mut plain = Plain {
+        output,
+        layout,
+        source: source.map(|source| {
+            (
+                source.path,
+                source.text.lines().collect(),
+                SourceMap::new(source.text),
+            )
+        }),
+        index: 0,
+        line: layout.line,
+        directive: None,
+        mappings: vec![],
+    };
+    plain.block(expressions, 1)?;
+    Ok(plain.mappings)
+}
+
+/// Line `<CODE>` starts on in `template`.
+pub(crate) fn code_line(template: &str) -> usize {
+    let end = template.find("<CODE>").unwrap_or(template.len());
+    template[..end].matches('\n').count() + 1
+}
+
+struct Plain<'a> {
+    output: &'a mut dyn Write,
+    layout: &'a Layout<'a>,
+    source: Option<(&'a str, Vec<&'a str>, SourceMap)>,
+    /// Index of the next expression in the source map, which counts like the opcodes.
+    index: usize,
+    /// Line of the emitted file the next line is written to.
+    line: usize,
+    /// Source line the last `#line` directive gives the next line.
+    directive: Option<usize>,
+    mappings: Vec<Mapping>,
+}
+
+impl Plain<'_> {
+    fn block(&mut self, expressions: &[Expression], depth: usize) -> io::Result<()> {
+        let mut rest = expressions;
+
+        while let Some(expression) = rest.first() {
+            if let Expression::Loop(body) = expression {
+                self.comment(depth, self.index, self.index + Self::width(body) + 1)?;
+                self.statement(depth, self.layout.open)?;
+
+                self.block(body, depth + 1)?;
+                match self.layout.close {
+                    Some(close) => self.statement(depth, close)?,
+                    None => {
+                        if body.is_empty() {
+                            self.line(depth + 1, "pass")?;
+                        }
+                        self.index += 1;
+                    }
+                }
+                rest = &rest[1..];
+                continue;
+            }
+
+            let length = rest
+                .iter()
+                .position(|expression| matches!(expression, Expression::Loop(_)))
+                .unwrap_or(rest.len());
+            self.comment(depth, self.index, self.index + length - 1)?;
+
+            for expression in &rest[..length] {
+                let statement = (self.layout.statement)(expression);
+                self.statement(depth, &statement)?;
+            }
+            rest = &rest[length..];
+        }
+        Ok(())
+    }
+
+    /// Number of source map entries `expressions` take up, counting both brackets.
+    fn width(expressions: &[Expression]) -> usize {
+        expressions
+            .iter()
+            .map(|expression| match expression {
+                Expression::Loop(body) => Self::width(body) + 2,
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// Writes the lines of the next expression or bracket in the source map.
+    fn statement(&mut self, depth: usize, text: &str) -> io::Result<()> {
+        let index = self.index;
+        self.index += 1;
+
+        let source = self
+            .source
+            .as_ref()
+            .and_then(|(path, _, map)| Some((escape(path, Escape::C), map.span(index)?)));
+
+        for text in text.lines() {
+            if let Some((path, span)) = &source {
+                if self.layout.directives && self.directive != Some(span.start.line) {
+                    let directive = format!("#line {} \"{path}\"", span.start.line);
+                    self.line(depth, &directive)?;
+                    self.directive = Some(span.start.line);
+                }
+                self.mappings.push(Mapping {
+                    line: self.line,
+                    span: *span,
+                });
+            }
+            self.line(depth, text)?;
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, depth: usize, text: &str) -> io::Result<()> {
+        self.line += 1;
+        self.directive = self.directive.map(|line| line + 1);
+        writeln!(self.output, "{:width$}{text}", "", width = depth * 4)
+    }
+
+    fn comment(&mut self, depth: usize, first: usize, last: usize) -> io::Result<()> {
+        if !self.layout.comments {
+            return Ok(());
+        }
+        let Some((_, lines, map)) = &self.source else {
+            return Ok(());
+        };
+        let (Some(first), Some(last)) = (map.span(first), map.span(last)) else {
+            return Ok(());
+        };
+
+        let mut text = commands(
+            lines,
+            Span {
+                start: first.start,
+                end: last.end,
+            },
+        );
+        if text.len() > COMMENT_LENGTH {
+            text.truncate(COMMENT_LENGTH);
+            text.push_str("...");
+        }
+        self.line(depth, &format!("{} {text}", self.layout.comment))
+    }
+}
+
+/// Writes `mappings` as JSON: every line of `file` with the span of `source` it came from.
+pub fn write_source_map(
+    output: &mut dyn Write,
+    file: &str,
+    source: &str,
+    mappings: &[Mapping],
+) -> io::Result<()> {
+    write!(
+        output,
+        "{{\"file\":\"{}\",\"source\":\"{}\",\"mappings\":[",
+        escape(file, Escape::Json),
+        escape(source, Escape::Json)
+    )?;
+    for (number, mapping) in mappings.iter().enumerate() {
+        if number > 0 {
+            write!(output, ",")?;
+        }
+        write!(
+            output,
+            "{{\"line\":{},\"span\":\"{}\"}}",
+            mapping.line, mapping.span
+        )?;
+    }
+    writeln!(output, "]}}")
+}
+
+/// The string literals [`escape`] writes, which spell other control characters apart.
+#[derive(Clone, Copy)]
+enum Escape {
+    /// As three octal digits, since C has no universal character names for controls.
+    C,
+    Json,
+}
+
+/// Escapes `text` for a string literal in C or JSON.
+fn escape(text: &str, escape: Escape) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for char in text.chars() {
+        match char {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            char if char.is_ascii_control() => match escape {
+                Escape::C => escaped.push_str(&format!("\\{:03o}", char as u32)),
+                Escape::Json => escaped.push_str(&format!("\\u{:04x}", char as u32)),
+            },
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+/// The brainfuck commands inside `span`, without whitespace or comments.
+fn commands(lines: &[&str], span: Span) -> String {
+    let mut text = String::new();
+
+    for line in span.start.line..=span.end.line {
+        let Some(characters) = lines.get(line - 1) else {
+            break;
+        };
+        for (index, character) in characters.chars().enumerate() {
+            let position = Position {
+                line,
+                column: index + 1,
+            };
+            if span.contains(position) && "+-<>[].,".contains(character) {
+                text.push(character);
+            }
+        }
+    }
+    text
+}
+
+/// Builds and runs emitted code for the tests of the emitters. Every tool is looked up
+/// first and a test needing a missing one passes without running, as no machine is
+/// expected to hold every toolchain.
+#[cfg(test)]
+pub(crate) mod toolchain {
+    use std::{
+        io::Write,
+        path::{Path, PathBuf},
+        process::{Command, Output, Stdio},
+    };
+
+    use crate::backends::{CellWidth, Config, Eof};
+
+    /// What the emitter tests run a program with: its name, text, config and input, to
+    /// its output, or `None` when the tools it needs are missing.
+    pub type Run<'a> = &'a dyn Fn(&str, &str, Config, &[u8]) -> Option<Vec<u8>>;
+
+    /// Set to fail the tests whose tools are missing instead of passing them unrun, so a
+    /// CI run with every toolchain installed cannot go green by skipping them.
+    const REQUIRE: &str = "BF_REQUIRE_TOOLCHAINS";
+
+    /// Whether all of `tools` start, telling which one does not otherwise, or panicking
+    /// when [`REQUIRE`] is set.
+    pub fn available(tools: &[&str]) -> bool {
+        match tools
+            .iter()
+            .find(|tool| Command::new(tool).arg("--version").output().is_err())
+        {
+            Some(tool) if std::env::var_os(REQUIRE).is_some() => {
+                panic!("`{tool}` is not installed and {REQUIRE} is set")
+            }
+            Some(tool) => {
+                eprintln!("skipped, `{tool}` is not installed");
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// `file` in a scratch directory of `backend`, one per test process.
+    pub fn path(backend: &str, file: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("bf-{backend}-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory.join(file)
+    }
+
+    /// Runs `tool` with `arguments` and then `-o output input`, which has to succeed.
+    pub fn build(tool: &str, arguments: &[&str], input: &Path, output: &Path) {
+        let status = Command::new(tool)
+            .args(arguments)
+            .arg("-o")
+            .arg(output)
+            .arg(input)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{tool} failed on {}", input.display());
+    }
+
+    /// Runs `command` with `input` on its stdin and waits for it to exit.
+    pub fn run(command: &mut Command, input: &[u8]) -> Output {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // programs that never read may be gone before their input is written
+        let _ = child.stdin.take().unwrap().write_all(input);
+        child.wait_with_output().unwrap()
+    }
+
+    /// `data/programs/cellsize.bf` on each [`CellWidth`].
+    pub fn cell_widths(run: Run) {
+        let text = std::fs::read_to_string(format!(
+            "{}/../../data/programs/cellsize.bf",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+
+        for (cell_width, expected) in [
+            (CellWidth::U8, "8 bit cells\n"),
+            (CellWidth::U16, "16 bit cells\n"),
+            (CellWidth::U32, "32 bit cells\n"),
+        ] {
+            let config = Config {
+                cell_width,
+                ..Config::default()
+            };
+            let Some(output) = run(&format!("cellsize{cell_width}"), &text, config, b"") else {
+                return;
+            };
+            assert_eq!(String::from_utf8(output).unwrap(), expected, "{cell_width}");
+        }
+    }
+
+    /// A `,` into a cell holding 7, at the end of input on each [`Eof`] and before it.
+    pub fn eofs(run: Run) {
+        for (eof, input, expected) in [
+            (Eof::Zero, &b""[..], 0),
+            (Eof::Unchanged, b"", 7),
+            (Eof::MinusOne, b"", 255),
+            (Eof::Zero, b"a", b'a'),
+        ] {
+            let config = Config {
+                eof,
+                ..Config::default()
+            };
+            let name = format!("eof-{eof}-{}", input.len());
+            let Some(output) = run(&name, "+++++++,.", config, input) else {
+                return;
+            };
+            assert_eq!(output, [expected], "{eof} on {input:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backends::{
+        Source,
+        transpilers::{
+            Escape, Layout, escape, validate_template, write_plain, write_source_map,
+            write_template,
+        },
+    };
+    use crate::core::{ir::Expression, pipeline::Pipeline};
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn layout(comments: bool, directives: bool) -> Layout<'static> {
+        Layout {
+            open: "loop {",
+            close: Some("}"),
+            comment: "//",
+            statement: &|expression| match expression {
+                Expression::Loop(_) => unreachable!(),
+                expression => format!("{expression:?};"),
+            },
+            comments,
+            directives,
+            line: 1,
+        }
+    }
+
+    #[test]
+    fn template() {
+        let mut output = vec![];
+        write_template(
+            &mut output,
+            "#include <stdio.h>\nint a[<LENGTH>]; <CODE> if (a < b) <<LENGTH>>",
+            &[("LENGTH", "3")],
+            &mut |output| output.write_all(b"code();"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "#include <stdio.h>\nint a[3]; code(); if (a < b) <3>"
+        );
+    }
+
+    #[test_case("#include <stdio.h>\n<CODE> Vec<T>", &[] => matches Ok(()); "angle brackets")]
+    #[test_case("int tape[<CELLS>]; <CODE>", &[] => matches Err(_); "unknown placeholder")]
+    #[test_case("int main() {}", &[] => matches Err(_); "missing code")]
+    #[test_case("<CODE> <CODE>", &[] => matches Err(_); "code twice")]
+    #[test_case("int *p, c; <CODE>", &["p", "c"] => matches Ok(()); "names")]
+    #[test_case("int *ptr, c; <CODE>", &["p", "c"] => matches Err(_); "missing name")]
+    fn validate(template: &str, names: &[&str]) -> std::io::Result<()> {
+        validate_template(template, &["CELL"], names)
+    }
+
+    #[test]
+    fn plain() {
+        let text = "copy it\n++[->+<]>\n[.[-]<]";
+        let mut output = vec![];
+        write_plain(
+            &mut output,
+            &Pipeline::execute(text),
+            Some(&Source { path: "a.bf", text }),
+            &layout(true, false),
+        )
+        .unwrap();
+
+        let expected = "    // ++[->+<]>\n\
+                        \x20   IncVal(2);\n\
+                        \x20   MulVal(1, 1);\n\
+                        \x20   Clear;\n\
+                        \x20   IncPtr(1);\n\
+                        \x20   // [.[-]<]\n\
+                        \x20   loop {\n\
+                        \x20       // .[-]<\n\
+                        \x20       Output;\n\
+                        \x20       Clear;\n\
+                        \x20       DecPtr(1);\n\
+                        \x20   }\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn indented() {
+        let expressions = vec![
+            Expression::IncVal(1),
+            Expression::Loop(vec![]),
+            Expression::Loop(vec![Expression::Output]),
+        ];
+        let layout = Layout {
+            open: "while:",
+            close: None,
+            comment: "#",
+            ..layout(false, false)
+        };
+        let mut output = vec![];
+        write_plain(&mut output, &expressions, None, &layout).unwrap();
+
+        let expected = "    IncVal(1);\n\
+                        \x20   while:\n\
+                        \x20       pass\n\
+                        \x20   while:\n\
+                        \x20       Output;\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn line_directives() {
+        let text = "+>\n\n.";
+        let mut output = vec![];
+        let mappings = write_plain(
+            &mut output,
+            &Pipeline::execute(text),
+            Some(&Source { path: "a.bf", text }),
+            &layout(false, true),
+        )
+        .unwrap();
+
+        let expected = "    #line 1 \"a.bf\"\n\
+                        \x20   IncVal(1);\n\
+                        \x20   #line 1 \"a.bf\"\n\
+                        \x20   IncPtr(1);\n\
+                        \x20   #line 3 \"a.bf\"\n\
+                        \x20   Output;\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let mut map = vec![];
+        write_source_map(&mut map, "a.c", "a.bf", &mappings).unwrap();
+        assert_eq!(
+            String::from_utf8(map).unwrap(),
+            "{\"file\":\"a.c\",\"source\":\"a.bf\",\"mappings\":[\
+             {\"line\":2,\"span\":\"1:1\"},{\"line\":4,\"span\":\"1:2\"},{\"line\":6,\"span\":\"3:1\"}]}\n"
+        );
+    }
 
-pub trait Transpiler {
-    fn transpile(expressions: &[Expression]) -> String;
+    #[test_case("a \"b\"\\c.bf", Escape::C, "a \\\"b\\\"\\\\c.bf"; "quotes and backslashes")]
+    #[test_case("a\nb\tc\u{1}d\u{7f}é", Escape::C, "a\\nb\\tc\\001d\\177é"; "c controls")]
+    #[test_case("a\nb\tc\u{1}d\u{7f}é", Escape::Json, "a\\nb\\tc\\u0001d\\u007fé"; "json controls")]
+    fn escapes(text: &str, flavour: Escape, expected: &str) {
+        assert_eq!(escape(text, flavour), expected);
+    }
 }
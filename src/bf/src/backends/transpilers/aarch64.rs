@@ -0,0 +1,287 @@
+use std::io::{self, Write};
+
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Mapping, Source, Tape,
+        transpilers::{validate_template, write_template},
+    },
+    core::ir::Expression,
+};
+
+const RUNTIME: &str = include_str!("../../runtimes/aarch64.s");
+
+/// Emits AArch64 assembly for Linux in GNU `as` syntax, which talks to the kernel with
+/// `svc` and links into a static binary without libc, like the `x86_64` emitter. A
+/// [`Config::runtime`] template can use `<MEMORY_LENGTH>` for the tape size and
+/// `<CELL_SIZE>` for the bytes per cell, must keep the pointer in `x19`, provide `putchar`
+/// and `getchar` like the built-in one and hold `<CODE>` once.
+pub struct Transpiler;
+
+struct Generator<'a> {
+    output: &'a mut dyn Write,
+    /// Loads a cell zero extended into a `w` register.
+    load: &'static str,
+    /// Stores the low bytes of a `w` register into a cell.
+    store: &'static str,
+    bytes: usize,
+    eof: Eof,
+    labels: usize,
+}
+
+impl Generator<'_> {
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels
+    }
+
+    /// `add` or `sub` of `amount` bytes, through `x1` when it does not fit an immediate.
+    fn offset(&mut self, target: &str, source: &str, amount: isize) -> io::Result<()> {
+        let operation = if amount < 0 { "sub" } else { "add" };
+        let amount = amount.unsigned_abs();
+        if amount < 4096 {
+            return writeln!(self.output, "    {operation} {target}, {source}, #{amount}");
+        }
+
+        writeln!(self.output, "    mov x1, #{}", amount & 0xffff)?;
+        for shift in [16, 32, 48] {
+            let part = (amount as u64 >> shift) & 0xffff;
+            if part != 0 {
+                writeln!(self.output, "    movk x1, #{part}, lsl #{shift}")?;
+            }
+        }
+        writeln!(self.output, "    {operation} {target}, {source}, x1")
+    }
+
+    fn load(&mut self, register: &str, address: &str) -> io::Result<()> {
+        writeln!(self.output, "    {} {register}, [{address}]", self.load)
+    }
+
+    fn store(&mut self, register: &str, address: &str) -> io::Result<()> {
+        writeln!(self.output, "    {} {register}, [{address}]", self.store)
+    }
+
+    /// `[>]` and `[<]` and wider steps, as a load and a move per cell.
+    fn scan(&mut self, step: isize) -> io::Result<()> {
+        let label = self.label();
+        writeln!(self.output, "    b .Lscan{label}")?;
+        writeln!(self.output, ".Lstep{label}:")?;
+        self.offset("x19", "x19", step * self.bytes as isize)?;
+        writeln!(self.output, ".Lscan{label}:")?;
+        self.load("w0", "x19")?;
+        writeln!(self.output, "    cbnz w0, .Lstep{label}")
+    }
+
+    fn transpile(&mut self, expressions: &[Expression]) -> io::Result<()> {
+        for expression in expressions {
+            match expression {
+                Expression::IncVal(amount) => {
+                    self.load("w0", "x19")?;
+                    writeln!(self.output, "    add w0, w0, #{amount}")?;
+                    self.store("w0", "x19")?;
+                }
+                Expression::DecVal(amount) => {
+                    self.load("w0", "x19")?;
+                    writeln!(self.output, "    sub w0, w0, #{amount}")?;
+                    self.store("w0", "x19")?;
+                }
+                Expression::IncPtr(amount) => {
+                    self.offset("x19", "x19", (amount * self.bytes) as isize)?;
+                }
+                Expression::DecPtr(amount) => {
+                    self.offset("x19", "x19", -((amount * self.bytes) as isize))?;
+                }
+                Expression::MulVal(offset, amount) => {
+                    // skipped for an empty cell, whose target may lie off the tape
+                    self.load("w0", "x19")?;
+                    writeln!(self.output, "    cbz w0, 1f")?;
+                    self.offset("x2", "x19", offset * self.bytes as isize)?;
+                    self.load("w1", "x2")?;
+                    writeln!(self.output, "    mov w3, #{amount}")?;
+                    writeln!(self.output, "    madd w1, w0, w3, w1")?;
+                    self.store("w1", "x2")?;
+                    writeln!(self.output, "1:")?;
+                }
+                Expression::Clear => self.store("wzr", "x19")?,
+                Expression::Loop(body) => match body.as_slice() {
+                    [Expression::IncPtr(step)] => self.scan(*step as isize)?,
+                    [Expression::DecPtr(step)] => self.scan(-(*step as isize))?,
+                    _ => {
+                        let label = self.label();
+                        self.load("w0", "x19")?;
+                        writeln!(self.output, "    cbz w0, .Lend{label}")?;
+                        writeln!(self.output, ".Lbody{label}:")?;
+                        self.transpile(body)?;
+                        self.load("w0", "x19")?;
+                        writeln!(self.output, "    cbnz w0, .Lbody{label}")?;
+                        writeln!(self.output, ".Lend{label}:")?;
+                    }
+                },
+                Expression::Output => {
+                    self.load("w0", "x19")?;
+                    writeln!(self.output, "    bl putchar")?;
+                }
+                Expression::Input => {
+                    writeln!(self.output, "    bl getchar")?;
+                    match self.eof {
+                        Eof::Zero => {
+                            writeln!(self.output, "    cmn w0, #1")?;
+                            writeln!(self.output, "    csel w0, wzr, w0, eq")?;
+                            self.store("w0", "x19")?;
+                        }
+                        Eof::Unchanged => {
+                            writeln!(self.output, "    cmn w0, #1")?;
+                            writeln!(self.output, "    b.eq 1f")?;
+                            self.store("w0", "x19")?;
+                            writeln!(self.output, "1:")?;
+                        }
+                        // the -1 getchar returns is already all ones
+                        Eof::MinusOne => self.store("w0", "x19")?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        "aarch64"
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: CellWidth::ALL,
+            tape_sizes: 1..=usize::MAX,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Emitter for Transpiler {
+    fn extension(&self, _config: &Config) -> &'static str {
+        "s"
+    }
+
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        _source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+        if config.line_directives || config.tape != Tape::Unchecked {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "aarch64 supports neither #line directives nor checked or growing tapes",
+            ));
+        }
+
+        let (load, store) = match config.cell_width {
+            CellWidth::U8 => ("ldrb", "strb"),
+            CellWidth::U16 => ("ldrh", "strh"),
+            CellWidth::U32 => ("ldr", "str"),
+        };
+        let bytes = config.cell_width.bits() as usize / 8;
+        let (memory_length, cell_size) = (config.tape_size.to_string(), bytes.to_string());
+        let template = config.runtime.as_deref().unwrap_or(RUNTIME);
+        let values = [
+            ("MEMORY_LENGTH", memory_length.as_str()),
+            ("CELL_SIZE", &cell_size),
+        ];
+        validate_template(template, &values.map(|(name, _)| name), &[])?;
+
+        write_template(output, template, &values, &mut |output| {
+            Generator {
+                output,
+                load,
+                store,
+                bytes,
+                eof: config.eof,
+                labels: 0,
+            }
+            .transpile(expressions)
+        })?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use crate::backends::{
+        CellWidth, Config, Emitter, Eof,
+        transpilers::{aarch64::Transpiler, toolchain},
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    /// Scans, large moves, multiplication and input at the end of it.
+    fn features() -> String {
+        format!("+>>+>>,[<]>{}<<<<<<<<<[>+++<-].", ">".repeat(5000))
+    }
+
+    fn emit(text: &str, config: &Config) -> String {
+        let mut code = vec![];
+        Transpiler
+            .emit(&Pipeline::execute(text), None, config, &mut code)
+            .unwrap();
+        String::from_utf8(code).unwrap()
+    }
+
+    fn read(path: &str) -> String {
+        std::fs::read_to_string(format!("{}/../../{path}", env!("CARGO_MANIFEST_DIR"))).unwrap()
+    }
+
+    #[test]
+    fn golden_hello_world() {
+        let text = read("data/programs/HelloWorld.bf");
+        assert_eq!(
+            emit(&text, &Config::default()),
+            read("data/golden/aarch64/HelloWorld.s")
+        );
+    }
+
+    #[test]
+    fn golden_features() {
+        let config = Config {
+            cell_width: CellWidth::U16,
+            eof: Eof::Unchanged,
+            ..Config::default()
+        };
+        assert_eq!(
+            emit(&features(), &config),
+            read("data/golden/aarch64/features.s")
+        );
+    }
+
+    /// Needs the GNU cross assembler and linker and qemu-user, and passes without them.
+    #[test_case("HelloWorld", &read("data/programs/HelloWorld.bf"), b"Hello, World!")]
+    #[test_case("features", &features(), &[0])]
+    fn run(name: &str, text: &str, expected: &[u8]) {
+        let tools = [
+            "aarch64-linux-gnu-as",
+            "aarch64-linux-gnu-ld",
+            "qemu-aarch64",
+        ];
+        if !toolchain::available(&tools) {
+            return;
+        }
+
+        let (source, object, binary) = (
+            toolchain::path("aarch64", &format!("{name}.s")),
+            toolchain::path("aarch64", &format!("{name}.o")),
+            toolchain::path("aarch64", name),
+        );
+        std::fs::write(&source, emit(text, &Config::default())).unwrap();
+        toolchain::build(tools[0], &[], &source, &object);
+        toolchain::build(tools[1], &[], &object, &binary);
+
+        let output = toolchain::run(Command::new(tools[2]).arg(&binary), b"");
+        assert_eq!(output.stdout, expected);
+    }
+}
@@ -1,68 +1,358 @@
-use crate::{backends, core::ir::Expression};
+use std::io::{self, Write};
 
-const MEMORY: &str = "MEMORY";
-const POINTER: &str = "POINTER";
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Mapping, Source, Style, Tape,
+        transpilers::{Layout, code_line, render, validate_template, write_plain, write_template},
+    },
+    core::ir::Expression,
+};
 
 const RUNTIME: &str = include_str!("../../runtimes/runtime.c");
+const PLAIN_RUNTIME: &str = include_str!("../../runtimes/plain.c");
 
+const UNCHECKED: &str = include_str!("../../runtimes/tapes/unchecked.c");
+const CHECKED: &str = include_str!("../../runtimes/tapes/checked.c");
+const GROWING: &str = include_str!("../../runtimes/tapes/growing.c");
+const PLAIN_UNCHECKED: &str = include_str!("../../runtimes/tapes/plain_unchecked.c");
+const PLAIN_CHECKED: &str = include_str!("../../runtimes/tapes/plain_checked.c");
+const PLAIN_GROWING: &str = include_str!("../../runtimes/tapes/plain_growing.c");
+
+/// Names the statements of the plain style use: the cell type, the pointer into the tape
+/// and the `int` that `getchar` reads into.
+const PLAIN_NAMES: &[&str] = &["cell", "p", "c"];
+
+/// The macros the code of the macro style is written in.
+const MACROS: &[&str] = &[
+    "INC_VAL_BY",
+    "DEC_VAL_BY",
+    "INC_PTR_BY",
+    "DEC_PTR_BY",
+    "MUL_VAL_BY",
+    "CLEAR",
+    "LOOP",
+    "OUTPUT",
+    "INPUT",
+];
+
+/// Emits C. A [`Config::runtime`] template can use `<CELL>` for the cell type,
+/// `<MEMORY_LENGTH>` for the tape size and `<TAPE>` for the tape and the functions the
+/// [`Tape`] needs, plus `<EOF>` for what `,` stores at the end of input in the macro
+/// style, and must hold `<CODE>` once. In the plain style it must declare the `cell` type
+/// and `cell *p` and `int c` in the scope of `<CODE>` like the built-in one, and in the
+/// macro style define every one of the [`MACROS`].
 pub struct Transpiler;
 
 impl Transpiler {
-    fn do_transpile(depth: usize, expressions: &[Expression]) -> String {
-        let mut buffer = String::with_capacity(1024);
-
+    fn do_transpile(
+        output: &mut dyn Write,
+        depth: usize,
+        expressions: &[Expression],
+    ) -> io::Result<()> {
         for expression in expressions {
             for _ in 0..depth {
-                buffer.push('\t')
+                output.write_all(b"\t")?;
             }
 
             match expression {
                 Expression::IncVal(amount) => {
-                    buffer.push_str(&format!("INC_VAL_BY({amount})"));
+                    write!(output, "INC_VAL_BY({amount})")?;
                 }
                 Expression::DecVal(amount) => {
-                    buffer.push_str(&format!("DEC_VAL_BY({amount})"));
+                    write!(output, "DEC_VAL_BY({amount})")?;
                 }
                 Expression::IncPtr(amount) => {
-                    buffer.push_str(&format!("INC_PTR_BY({amount})"));
+                    write!(output, "INC_PTR_BY({amount})")?;
                 }
                 Expression::DecPtr(amount) => {
-                    buffer.push_str(&format!("DEC_PTR_BY({amount})"));
+                    write!(output, "DEC_PTR_BY({amount})")?;
                 }
                 Expression::Loop(expression) => {
-                    buffer.push_str("LOOP(\n");
-                    buffer.push_str(&Self::do_transpile(depth + 1, expression));
+                    output.write_all(b"LOOP(\n")?;
+                    Self::do_transpile(output, depth + 1, expression)?;
 
                     for _ in 0..depth {
-                        buffer.push('\t');
+                        output.write_all(b"\t")?;
                     }
-                    buffer.push(')');
+                    output.write_all(b")")?;
                 }
                 Expression::Output => {
-                    buffer.push_str("OUTPUT");
+                    output.write_all(b"OUTPUT")?;
                 }
                 Expression::Input => {
-                    todo!()
+                    output.write_all(b"INPUT")?;
                 }
 
                 Expression::Clear => {
-                    buffer.push_str("CLEAR");
+                    output.write_all(b"CLEAR")?;
                 }
 
                 Expression::MulVal(offset, amount) => {
-                    buffer.push_str(&format!("MUL_VAL_BY({offset}, {amount})"));
+                    write!(output, "MUL_VAL_BY({offset}, {amount})")?;
                 }
             }
 
-            buffer.push_str(";\n");
+            output.write_all(b";\n")?;
+        }
+        Ok(())
+    }
+
+    fn statement(expression: &Expression, tape: Tape, eof: &str) -> String {
+        match expression {
+            Expression::IncVal(amount) => format!("p[0] += {amount};"),
+            Expression::DecVal(amount) => format!("p[0] -= {amount};"),
+            Expression::IncPtr(amount) if tape == Tape::Unchecked => format!("p += {amount};"),
+            Expression::DecPtr(amount) if tape == Tape::Unchecked => format!("p -= {amount};"),
+            Expression::IncPtr(amount) => format!("p = shift(p, {amount});"),
+            Expression::DecPtr(amount) => format!("p = shift(p, -{amount});"),
+            Expression::MulVal(offset, amount) if tape == Tape::Unchecked => {
+                format!("p[{offset}] += p[0] * {amount};")
+            }
+            Expression::MulVal(offset, amount) => {
+                format!(
+                    "if (p[0]) {{\n    p = reach(p, {offset});\n    p[{offset}] += p[0] * {amount};\n}}"
+                )
+            }
+            Expression::Clear => "p[0] = 0;".to_string(),
+            Expression::Output => "putchar(p[0]);".to_string(),
+            Expression::Input => format!("p[0] = (c = getchar()) == EOF ? {eof} : c;"),
+            Expression::Loop(_) => unreachable!("loops are written as blocks"),
+        }
+    }
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        "c"
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: CellWidth::ALL,
+            tape_sizes: 1..=usize::MAX,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
         }
-        buffer
     }
 }
 
-impl backends::transpilers::Transpiler for Transpiler {
-    fn transpile(expressions: &[Expression]) -> String {
-        let code = Self::do_transpile(1, expressions);
-        RUNTIME.replace("<CODE>", &code)
+impl Emitter for Transpiler {
+    fn extension(&self, _config: &Config) -> &'static str {
+        "c"
+    }
+
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+
+        let cell = format!("uint{}_t", config.cell_width);
+        let memory_length = config.tape_size.to_string();
+
+        if config.style == Style::Plain {
+            let eof = match config.eof {
+                Eof::Zero => "0",
+                Eof::Unchanged => "p[0]",
+                Eof::MinusOne => "(cell) -1",
+            };
+            let tape = match config.tape {
+                Tape::Unchecked => PLAIN_UNCHECKED,
+                Tape::Checked => PLAIN_CHECKED,
+                Tape::Growing => PLAIN_GROWING,
+            };
+            let tape = render(tape, &[("MEMORY_LENGTH", &memory_length)]);
+            let template = config.runtime.as_deref().unwrap_or(PLAIN_RUNTIME);
+            let values = [
+                ("CELL", cell.as_str()),
+                ("MEMORY_LENGTH", &memory_length),
+                ("TAPE", &tape),
+            ];
+            validate_template(template, &values.map(|(name, _)| name), PLAIN_NAMES)?;
+
+            let layout = Layout {
+                open: "while (*p) {",
+                close: Some("}"),
+                comment: "//",
+                statement: &|expression| Self::statement(expression, config.tape, eof),
+                comments: config.comments,
+                directives: config.line_directives,
+                line: code_line(template),
+            };
+
+            let mut mappings = vec![];
+            write_template(output, template, &values, &mut |output| {
+                mappings = write_plain(output, expressions, source, &layout)?;
+                Ok(())
+            })?;
+            return Ok(mappings);
+        }
+        if config.line_directives {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "#line directives need the plain style",
+            ));
+        }
+
+        let eof = match config.eof {
+            Eof::Zero => "0",
+            Eof::Unchanged => "MEMORY[POINTER]",
+            Eof::MinusOne => "(cell) -1",
+        };
+        let tape = match config.tape {
+            Tape::Unchecked => UNCHECKED,
+            Tape::Checked => CHECKED,
+            Tape::Growing => GROWING,
+        };
+        let template = config.runtime.as_deref().unwrap_or(RUNTIME);
+        let values = [
+            ("CELL", cell.as_str()),
+            ("MEMORY_LENGTH", &memory_length),
+            ("TAPE", tape.trim_end()),
+            ("EOF", eof),
+        ];
+        validate_template(template, &values.map(|(name, _)| name), MACROS)?;
+
+        write_template(output, template, &values, &mut |output| {
+            Self::do_transpile(output, 1, expressions)
+        })?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::{Command, Output};
+
+    use crate::backends::{
+        Config, Emitter, Source, Style, Tape,
+        transpilers::{c::Transpiler, toolchain},
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn run(name: &str, text: &str, config: Config, input: &[u8]) -> Option<Output> {
+        if !toolchain::available(&["cc"]) {
+            return None;
+        }
+        let (source, binary) = (
+            toolchain::path("c", &format!("{name}.c")),
+            toolchain::path("c", name),
+        );
+
+        let mut code = vec![];
+        let program = Source {
+            path: &format!("{name}.bf"),
+            text,
+        };
+        Transpiler
+            .emit(&Pipeline::execute(text), Some(&program), &config, &mut code)
+            .unwrap();
+        std::fs::write(&source, code).unwrap();
+        toolchain::build("cc", &[], &source, &binary);
+
+        Some(toolchain::run(&mut Command::new(&binary), input))
+    }
+
+    #[test]
+    fn cell_widths() {
+        toolchain::cell_widths(&|name, text, config, input| {
+            run(name, text, config, input).map(|output| output.stdout)
+        });
+    }
+
+    #[test_case(Style::Macros)]
+    #[test_case(Style::Plain)]
+    fn eofs(style: Style) {
+        toolchain::eofs(&|name, text, config, input| {
+            let config = Config { style, ..config };
+            run(&format!("{name}-{style}"), text, config, input).map(|output| output.stdout)
+        });
+    }
+
+    #[test]
+    fn line_directives() {
+        let config = Config {
+            style: Style::Plain,
+            line_directives: true,
+            ..Config::default()
+        };
+
+        if let Some(output) = run("lines", "++++++++\n[>++++++<-]>\n+.", config, b"") {
+            assert_eq!(output.stdout, b"1");
+        }
+    }
+
+    #[test]
+    fn line_directives_need_plain_style() {
+        let config = Config {
+            line_directives: true,
+            ..Config::default()
+        };
+
+        let error = Transpiler
+            .emit(&Pipeline::execute("+."), None, &config, &mut vec![])
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test_case(
+        Tape::Checked,
+        Style::Macros,
+        "",
+        "cell 12, outside the tape of 10 cells"
+    )]
+    #[test_case(
+        Tape::Checked,
+        Style::Plain,
+        "",
+        "cell 12, outside the tape of 10 cells"
+    )]
+    #[test_case(Tape::Growing, Style::Macros, "!", "")]
+    #[test_case(Tape::Growing, Style::Plain, "!", "")]
+    fn tape(tape: Tape, style: Style, expected: &str, error: &str) {
+        let config = Config {
+            tape_size: 10,
+            tape,
+            style,
+            ..Config::default()
+        };
+        let text = ">>>>>>>>>>>>+++++++++++++++++++++++++++++++++[<<+>>-]<<.";
+
+        let Some(output) = run(&format!("tape-{tape}-{style}"), text, config, b"") else {
+            return;
+        };
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+        assert!(String::from_utf8(output.stderr).unwrap().contains(error));
+        assert_eq!(output.status.success(), error.is_empty());
+    }
+
+    #[test]
+    fn runtime_template() {
+        let config = Config {
+            style: Style::Plain,
+            runtime: Some(
+                "#include <stdint.h>\n\
+                 #include <stdio.h>\n\
+                 typedef <CELL> cell;\n\
+                 static cell tape[<MEMORY_LENGTH>];\n\
+                 static void program(void) {\n\
+                 cell *p = tape;\n\
+                 int c;\n\
+                 <CODE>}\n\
+                 int main(void) { program(); program(); return 0; }\n"
+                    .to_string(),
+            ),
+            ..Config::default()
+        };
+
+        let text = "[-]+++++++++++++++++++++++++++++++++.";
+        if let Some(output) = run("template", text, config, b"") {
+            assert_eq!(output.stdout, b"!!");
+        }
     }
 }
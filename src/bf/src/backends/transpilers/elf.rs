@@ -0,0 +1,371 @@
+use std::io::{self, Write};
+
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Mapping, Source, Tape,
+    },
+    core::ir::Expression,
+};
+
+/// Where the code is loaded, right after the headers.
+const TEXT: u32 = 0x40_0000;
+/// Where the zeroed tape, output buffer and input byte are mapped, far enough from
+/// [`TEXT`] for any program and low enough for 32-bit absolute addresses.
+const BSS: u32 = 0x1000_0000;
+const BUFFER_LENGTH: u32 = 4096;
+
+const HEADER_LENGTH: u32 = 64;
+const PROGRAM_HEADER_LENGTH: u32 = 56;
+
+#[derive(Clone, Copy)]
+enum Label {
+    Flush,
+    Putchar,
+    Getchar,
+}
+
+/// Writes a static x86-64 Linux executable with no toolchain at all: an ELF header, one
+/// segment for the code and one zeroed segment for the tape, like the `x86_64` emitter
+/// would after `as` and `ld`. `rbx` holds the address of the current cell and `r12` the
+/// number of buffered output bytes.
+pub struct Transpiler;
+
+struct Assembler {
+    code: Vec<u8>,
+    fixups: Vec<(usize, Label)>,
+    eof: Eof,
+    buffer: u32,
+    input: u32,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.emit(&value.to_le_bytes());
+    }
+
+    fn immediate(value: usize) -> u32 {
+        i32::try_from(value).expect("amount does not fit an immediate") as u32
+    }
+
+    /// `call` or `je` to a routine placed after the program.
+    fn emit_jump(&mut self, opcode: &[u8], label: Label) {
+        self.emit(opcode);
+        self.fixups.push((self.code.len(), label));
+        self.emit_u32(0);
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        let relative = target as i64 - (at as i64 + 4);
+        self.code[at..at + 4].copy_from_slice(&(relative as i32).to_le_bytes());
+    }
+
+    fn assemble(&mut self, expressions: &[Expression]) {
+        for expression in expressions {
+            match *expression {
+                // add byte [rbx], amount
+                Expression::IncVal(amount) => self.emit(&[0x80, 0x03, amount]),
+                // sub byte [rbx], amount
+                Expression::DecVal(amount) => self.emit(&[0x80, 0x2b, amount]),
+                Expression::IncPtr(amount) => {
+                    // add rbx, amount
+                    self.emit(&[0x48, 0x81, 0xc3]);
+                    self.emit_u32(Self::immediate(amount));
+                }
+                Expression::DecPtr(amount) => {
+                    // sub rbx, amount
+                    self.emit(&[0x48, 0x81, 0xeb]);
+                    self.emit_u32(Self::immediate(amount));
+                }
+                Expression::MulVal(offset, amount) => {
+                    // movzx eax, byte [rbx]; test eax, eax; jz over the next two
+                    self.emit(&[0x0f, 0xb6, 0x03, 0x85, 0xc0, 0x74, 12]);
+                    // imul eax, eax, amount
+                    self.emit(&[0x69, 0xc0]);
+                    self.emit_u32(amount as u32);
+                    // add byte [rbx + offset], al
+                    self.emit(&[0x00, 0x83]);
+                    self.emit_u32(i32::try_from(offset).expect("offset does not fit") as u32);
+                }
+                // mov byte [rbx], 0
+                Expression::Clear => self.emit(&[0xc6, 0x03, 0x00]),
+                Expression::Loop(ref body) => match body.as_slice() {
+                    [Expression::IncPtr(step)] => self.scan(0xc3, *step),
+                    [Expression::DecPtr(step)] => self.scan(0xeb, *step),
+                    _ => {
+                        // cmp byte [rbx], 0; je end
+                        self.emit(&[0x80, 0x3b, 0x00, 0x0f, 0x84]);
+                        let skip = self.code.len();
+                        self.emit_u32(0);
+
+                        let start = self.code.len();
+                        self.assemble(body);
+
+                        // cmp byte [rbx], 0; jne start
+                        self.emit(&[0x80, 0x3b, 0x00, 0x0f, 0x85]);
+                        let repeat = self.code.len();
+                        self.emit_u32(0);
+
+                        self.patch(repeat, start);
+                        let end = self.code.len();
+                        self.patch(skip, end);
+                    }
+                },
+                Expression::Output => {
+                    // movzx eax, byte [rbx]
+                    self.emit(&[0x0f, 0xb6, 0x03]);
+                    self.emit_jump(&[0xe8], Label::Putchar);
+                }
+                Expression::Input => {
+                    self.emit_jump(&[0xe8], Label::Getchar);
+                    match self.eof {
+                        // cmp eax, -1; jne over; xor eax, eax; mov [rbx], al
+                        Eof::Zero => self.emit(&[0x83, 0xf8, 0xff, 0x75, 0x02, 0x31, 0xc0]),
+                        // cmp eax, -1; je over the store
+                        Eof::Unchanged => self.emit(&[0x83, 0xf8, 0xff, 0x74, 0x02]),
+                        Eof::MinusOne => {}
+                    }
+                    // mov [rbx], al
+                    self.emit(&[0x88, 0x03]);
+                }
+            }
+        }
+    }
+
+    /// `[>]`, `[<]` and wider steps: jmp check; step: add/sub rbx, step; check: cmp byte
+    /// [rbx], 0; jne step.
+    fn scan(&mut self, operation: u8, step: usize) {
+        self.emit(&[0xeb, 0x07, 0x48, 0x81, operation]);
+        self.emit_u32(Self::immediate(step));
+        self.emit(&[0x80, 0x3b, 0x00, 0x75, 0xf4]);
+    }
+
+    fn flush(&mut self) {
+        // mov esi, buffer; mov rdx, r12
+        self.emit(&[0xbe]);
+        self.emit_u32(self.buffer);
+        self.emit(&[0x4c, 0x89, 0xe2]);
+        // loop: test rdx, rdx; jz done; mov eax, 1; mov edi, 1; syscall
+        self.emit(&[0x48, 0x85, 0xd2, 0x74, 25]);
+        self.emit(&[
+            0xb8, 0x01, 0x00, 0x00, 0x00, 0xbf, 0x01, 0x00, 0x00, 0x00, 0x0f, 0x05,
+        ]);
+        // test rax, rax; jle done; add rsi, rax; sub rdx, rax; jmp loop
+        self.emit(&[0x48, 0x85, 0xc0, 0x7e, 0x08]);
+        self.emit(&[0x48, 0x01, 0xc6, 0x48, 0x29, 0xc2, 0xeb, 0xe2]);
+        // done: xor r12d, r12d; ret
+        self.emit(&[0x45, 0x31, 0xe4, 0xc3]);
+    }
+
+    fn putchar(&mut self) {
+        // mov [r12 + buffer], al; inc r12
+        self.emit(&[0x41, 0x88, 0x84, 0x24]);
+        self.emit_u32(self.buffer);
+        self.emit(&[0x49, 0xff, 0xc4]);
+        // cmp r12, BUFFER_LENGTH; je flush; ret
+        self.emit(&[0x49, 0x81, 0xfc]);
+        self.emit_u32(BUFFER_LENGTH);
+        self.emit_jump(&[0x0f, 0x84], Label::Flush);
+        self.emit(&[0xc3]);
+    }
+
+    fn getchar(&mut self) {
+        // flush, so prompts show up before the program waits for input
+        self.emit_jump(&[0xe8], Label::Flush);
+        // xor eax, eax; xor edi, edi; mov esi, input; mov edx, 1; syscall
+        self.emit(&[0x31, 0xc0, 0x31, 0xff, 0xbe]);
+        self.emit_u32(self.input);
+        self.emit(&[0xba, 0x01, 0x00, 0x00, 0x00, 0x0f, 0x05]);
+        // test rax, rax; jle eof; movzx eax, byte [input]; ret
+        self.emit(&[0x48, 0x85, 0xc0, 0x7e, 0x09, 0x0f, 0xb6, 0x04, 0x25]);
+        self.emit_u32(self.input);
+        self.emit(&[0xc3]);
+        // eof: mov eax, -1; ret
+        self.emit(&[0xb8, 0xff, 0xff, 0xff, 0xff, 0xc3]);
+    }
+
+    fn finish(mut self, expressions: &[Expression]) -> Vec<u8> {
+        // mov ebx, BSS; xor r12d, r12d
+        self.emit(&[0xbb]);
+        self.emit_u32(BSS);
+        self.emit(&[0x45, 0x31, 0xe4]);
+
+        self.assemble(expressions);
+
+        self.emit_jump(&[0xe8], Label::Flush);
+        // mov eax, 60; xor edi, edi; syscall
+        self.emit(&[0xb8, 0x3c, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f, 0x05]);
+
+        let flush = self.code.len();
+        self.flush();
+        let putchar = self.code.len();
+        self.putchar();
+        let getchar = self.code.len();
+        self.getchar();
+
+        for (at, label) in std::mem::take(&mut self.fixups) {
+            let target = match label {
+                Label::Flush => flush,
+                Label::Putchar => putchar,
+                Label::Getchar => getchar,
+            };
+            self.patch(at, target);
+        }
+        self.code
+    }
+}
+
+fn program_header(
+    output: &mut dyn Write,
+    flags: u32,
+    address: u32,
+    file: u64,
+    memory: u64,
+) -> io::Result<()> {
+    output.write_all(&1u32.to_le_bytes())?;
+    output.write_all(&flags.to_le_bytes())?;
+    output.write_all(&0u64.to_le_bytes())?;
+    output.write_all(&(address as u64).to_le_bytes())?;
+    output.write_all(&(address as u64).to_le_bytes())?;
+    output.write_all(&file.to_le_bytes())?;
+    output.write_all(&memory.to_le_bytes())?;
+    output.write_all(&0x1000u64.to_le_bytes())
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        "elf"
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: &[CellWidth::U8],
+            tape_sizes: 1..=1 << 30,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Emitter for Transpiler {
+    fn extension(&self, _config: &Config) -> &'static str {
+        ""
+    }
+
+    fn executable(&self) -> bool {
+        true
+    }
+
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        _source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+        if config.line_directives || config.tape != Tape::Unchecked || config.runtime.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "elf supports neither #line directives, checked or growing tapes nor runtime templates",
+            ));
+        }
+
+        let buffer = BSS + (config.tape_size as u32).next_multiple_of(16);
+        let input = buffer + BUFFER_LENGTH;
+        let code = Assembler {
+            code: vec![],
+            fixups: vec![],
+            eof: config.eof,
+            buffer,
+            input,
+        }
+        .finish(expressions);
+
+        let headers = HEADER_LENGTH + 2 * PROGRAM_HEADER_LENGTH;
+        let length = headers as u64 + code.len() as u64;
+
+        output.write_all(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0")?;
+        // executable, x86-64, version 1
+        output.write_all(&[0x02, 0x00, 0x3e, 0x00, 0x01, 0x00, 0x00, 0x00])?;
+        output.write_all(&((TEXT + headers) as u64).to_le_bytes())?;
+        output.write_all(&(HEADER_LENGTH as u64).to_le_bytes())?;
+        // no sections and no flags
+        output.write_all(&[0; 12])?;
+        for value in [HEADER_LENGTH, PROGRAM_HEADER_LENGTH, 2, 64, 0, 0] {
+            output.write_all(&(value as u16).to_le_bytes())?;
+        }
+
+        // read and execute, then read and write
+        program_header(output, 5, TEXT, length, length)?;
+        program_header(output, 6, BSS, 0, (input + 1 - BSS) as u64)?;
+
+        output.write_all(&code)?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64", target_os = "linux"))]
+mod test {
+    use std::{os::unix::fs::PermissionsExt, process::Command};
+
+    use crate::backends::vm::{Interpreter, Vm, testing};
+    use crate::backends::{
+        Config, Emitter,
+        transpilers::{elf::Transpiler, toolchain},
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn run(name: &str, text: &str, config: Config, input: &[u8]) -> Vec<u8> {
+        let binary = toolchain::path("elf", name);
+
+        let mut code = vec![];
+        Transpiler
+            .emit(&Pipeline::execute(text), None, &config, &mut code)
+            .unwrap();
+        std::fs::write(&binary, code).unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = toolchain::run(&mut Command::new(&binary), input);
+        assert!(output.status.success());
+        output.stdout
+    }
+
+    #[test_case("HelloWorld.bf")]
+    #[test_case("bfeer.bf")]
+    #[test_case("cellsize.bf")]
+    #[test_case("char.bf")]
+    #[test_case("dbff2c.bf")]
+    #[test_case("hanoi.bf")]
+    #[test_case("mandelbrot.bf" => ignore)]
+    #[test_case("squares.bf")]
+    #[test_case("triangle.bf")]
+    fn matches_vm_on_programs(program: &str) {
+        let path = format!(
+            "{}/../../data/programs/{program}",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let text = std::fs::read_to_string(path).unwrap();
+        let input = b"+[,.]";
+
+        let vm = Vm::from(&Interpreter::interpret(&Pipeline::execute(&text)));
+        let (expected, ..) = testing::run(Box::new(vm), input);
+        assert_eq!(run(program, &text, Config::default(), input), expected);
+    }
+
+    #[test]
+    fn eofs() {
+        toolchain::eofs(&|name, text, config, input| Some(run(name, text, config, input)));
+    }
+
+    #[test]
+    fn scan() {
+        let text = "+>>+>>+>>>++++++++[<++++++++>-]<+<<<<<<[>>]<<.";
+        assert_eq!(run("scan", text, Config::default(), b""), b"A");
+    }
+}
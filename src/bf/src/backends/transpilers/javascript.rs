@@ -0,0 +1,262 @@
+use std::io::{self, Write};
+
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Mapping, Source, Tape,
+        transpilers::{Layout, code_line, validate_template, write_plain, write_template},
+        vm::START_POINTER,
+    },
+    core::ir::Expression,
+};
+
+const RUNTIME: &str = include_str!("../../runtimes/runtime.mjs");
+
+/// Runs the module when node is pointed at it, and leaves it alone when it is imported.
+const CLI: &str = r#"
+if (typeof process !== "undefined" && process.argv[1]) {
+    const { pathToFileURL } = await import("node:url");
+    if (import.meta.url === pathToFileURL(process.argv[1]).href) {
+        const { readFileSync } = await import("node:fs");
+        process.stdout.write(run(readFileSync(0)));
+    }
+}
+"#;
+
+/// What the module does besides exporting `run`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flavour {
+    /// Only exports `run`, for browsers and bundlers.
+    Module,
+    /// Also reads stdin and writes stdout when run with `node program.mjs`.
+    Node,
+}
+
+/// Emits an ES module exporting `run(input) -> Uint8Array` over a typed array tape, in
+/// the plain style whatever the [`Config::style`]. A [`Config::runtime`] template can use
+/// `<CELL>` for the typed array, `<MEMORY_LENGTH>` for the tape size, `<START>` for the
+/// cell the [`Vm`](crate::backends::vm::Vm) starts at, or the last one of a shorter tape,
+/// and `<CLI>` for the code of [`Flavour::Node`], must declare `tape`, `output`, `p` and `i` like the built-in
+/// one and hold `<CODE>` once.
+pub struct Transpiler {
+    pub flavour: Flavour,
+}
+
+impl Transpiler {
+    fn statement(expression: &Expression, eof: &str) -> String {
+        let at = |offset: isize| match offset {
+            0 => "tape[p]".to_string(),
+            offset if offset < 0 => format!("tape[p - {}]", -offset),
+            offset => format!("tape[p + {offset}]"),
+        };
+
+        match expression {
+            Expression::IncVal(amount) => format!("tape[p] += {amount};"),
+            Expression::DecVal(amount) => format!("tape[p] -= {amount};"),
+            Expression::IncPtr(amount) => format!("p += {amount};"),
+            Expression::DecPtr(amount) => format!("p -= {amount};"),
+            Expression::MulVal(offset, amount) => {
+                format!("{} += tape[p] * {amount};", at(*offset))
+            }
+            Expression::Clear => "tape[p] = 0;".to_string(),
+            Expression::Output => "output.push(tape[p]);".to_string(),
+            Expression::Input => format!("tape[p] = i < input.length ? input[i++] : {eof};"),
+            Expression::Loop(_) => unreachable!("loops are written as blocks"),
+        }
+    }
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        match self.flavour {
+            Flavour::Module => "javascript",
+            Flavour::Node => "node",
+        }
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: CellWidth::ALL,
+            // the longest typed array engines agree on
+            tape_sizes: 1..=u32::MAX as usize,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Emitter for Transpiler {
+    fn extension(&self, _config: &Config) -> &'static str {
+        "mjs"
+    }
+
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+        if config.line_directives || config.tape != Tape::Unchecked {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} supports neither #line directives nor checked or growing tapes",
+                    self.name()
+                ),
+            ));
+        }
+
+        let cell = format!("Uint{}Array", config.cell_width);
+        let memory_length = config.tape_size.to_string();
+        let start = START_POINTER.min(config.tape_size - 1).to_string();
+        let cli = match self.flavour {
+            Flavour::Module => "",
+            Flavour::Node => CLI,
+        };
+        let eof = match config.eof {
+            Eof::Zero => "0",
+            Eof::Unchanged => "tape[p]",
+            Eof::MinusOne => "-1",
+        };
+        let template = config.runtime.as_deref().unwrap_or(RUNTIME);
+        let values = [
+            ("CELL", cell.as_str()),
+            ("MEMORY_LENGTH", &memory_length),
+            ("START", &start),
+            ("CLI", cli),
+        ];
+        validate_template(
+            template,
+            &values.map(|(name, _)| name),
+            &["tape", "output", "p", "i"],
+        )?;
+
+        let layout = Layout {
+            open: "while (tape[p]) {",
+            close: Some("}"),
+            comment: "//",
+            statement: &|expression| Self::statement(expression, eof),
+            comments: config.comments,
+            directives: false,
+            line: code_line(template),
+        };
+
+        let mut mappings = vec![];
+        write_template(output, template, &values, &mut |output| {
+            mappings = write_plain(output, expressions, source, &layout)?;
+            Ok(())
+        })?;
+        Ok(mappings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use crate::backends::transpilers::{
+        javascript::{Flavour, Transpiler},
+        toolchain,
+    };
+    use crate::backends::vm::{Interpreter, Vm, testing};
+    use crate::backends::{Config, Emitter};
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    /// Imports the module instead of running it, to go through `run` itself.
+    const IMPORT: &str = "
+        import(require('node:url').pathToFileURL(process.argv[1]).href).then(({ run }) =>
+            process.stdout.write(run(require('node:fs').readFileSync(0))));";
+
+    fn run(
+        name: &str,
+        text: &str,
+        flavour: Flavour,
+        config: Config,
+        input: &[u8],
+    ) -> Option<Vec<u8>> {
+        if !toolchain::available(&["node"]) {
+            return None;
+        }
+        let module = toolchain::path("javascript", &format!("{name}-{flavour:?}.mjs"));
+
+        let mut code = vec![];
+        Transpiler { flavour }
+            .emit(&Pipeline::execute(text), None, &config, &mut code)
+            .unwrap();
+        std::fs::write(&module, code).unwrap();
+
+        let mut command = Command::new("node");
+        if flavour == Flavour::Module {
+            command.args(["-e", IMPORT]);
+        }
+        let output = toolchain::run(command.arg(&module), input);
+        assert!(output.status.success());
+        Some(output.stdout)
+    }
+
+    fn matches_vm(name: &str, text: &str) {
+        let input = b"+[,.]";
+
+        let Some(output) = run(name, text, Flavour::Node, Config::default(), input) else {
+            return;
+        };
+        let vm = Vm::from(&Interpreter::interpret(&Pipeline::execute(text)));
+        let (expected, ..) = testing::run(Box::new(vm), input);
+        assert_eq!(output, expected);
+    }
+
+    #[test_case("HelloWorld.bf")]
+    #[test_case("bfeer.bf")]
+    #[test_case("cellsize.bf")]
+    #[test_case("char.bf")]
+    #[test_case("dbff2c.bf")]
+    #[test_case("hanoi.bf")]
+    #[test_case("mandelbrot.bf" => ignore)]
+    #[test_case("squares.bf")]
+    #[test_case("triangle.bf")]
+    fn matches_vm_on_programs(program: &str) {
+        let path = format!(
+            "{}/../../data/programs/{program}",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let text = std::fs::read_to_string(path).unwrap();
+
+        matches_vm(program, &text);
+    }
+
+    #[test]
+    fn matches_vm_moving_left_first() {
+        matches_vm("left", &format!("<{}.", "+".repeat(33)));
+    }
+
+    #[test_case(Flavour::Module)]
+    #[test_case(Flavour::Node)]
+    fn cell_widths(flavour: Flavour) {
+        toolchain::cell_widths(&|name, text, config, input| {
+            run(name, text, flavour, config, input)
+        });
+    }
+
+    #[test_case(Flavour::Module)]
+    #[test_case(Flavour::Node)]
+    fn eofs(flavour: Flavour) {
+        toolchain::eofs(&|name, text, config, input| run(name, text, flavour, config, input));
+    }
+
+    #[test]
+    fn echo() {
+        let output = run(
+            "echo",
+            ",[.,]",
+            Flavour::Node,
+            Config::default(),
+            b"echo me",
+        );
+        if let Some(output) = output {
+            assert_eq!(output, b"echo me");
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::io::{self, Write};
+
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Mapping, Source, Tape,
+        transpilers::{validate_template, write_template},
+    },
+    core::ir::Expression,
+};
+
+const RUNTIME: &str = include_str!("../../runtimes/runtime.ll");
+
+/// Emits a textual LLVM IR module with opaque pointers. The tape is the global `@tape` and
+/// the index into it lives in the alloca `%pointer`, so loops need no phi nodes. A
+/// [`Config::runtime`] template can use `<CELL>` for the cell type and `<MEMORY_LENGTH>`
+/// for the tape size, must define both names and must hold `<CODE>` once.
+pub struct Transpiler;
+
+struct Generator<'a> {
+    output: &'a mut dyn Write,
+    cell: String,
+    eof: Eof,
+    /// Number of the next temporary or loop label.
+    next: usize,
+}
+
+impl Generator<'_> {
+    fn temporary(&mut self) -> String {
+        self.next += 1;
+        format!("%t{}", self.next)
+    }
+
+    /// Loads the pointer and returns the address of the cell `offset` away from it.
+    fn address(&mut self, offset: isize) -> io::Result<String> {
+        let pointer = self.temporary();
+        writeln!(self.output, "  {pointer} = load i64, ptr %pointer")?;
+
+        let index = match offset {
+            0 => pointer,
+            offset => {
+                let index = self.temporary();
+                writeln!(self.output, "  {index} = add i64 {pointer}, {offset}")?;
+                index
+            }
+        };
+
+        let address = self.temporary();
+        writeln!(
+            self.output,
+            "  {address} = getelementptr {}, ptr @tape, i64 {index}",
+            self.cell
+        )?;
+        Ok(address)
+    }
+
+    fn load(&mut self, address: &str) -> io::Result<String> {
+        let value = self.temporary();
+        writeln!(self.output, "  {value} = load {}, ptr {address}", self.cell)?;
+        Ok(value)
+    }
+
+    /// Applies `operation` with `amount` to the cell at `address`.
+    fn update(&mut self, address: &str, operation: &str, amount: &str) -> io::Result<()> {
+        let value = self.load(address)?;
+        let result = self.temporary();
+        writeln!(
+            self.output,
+            "  {result} = {operation} {} {value}, {amount}",
+            self.cell
+        )?;
+        writeln!(self.output, "  store {} {result}, ptr {address}", self.cell)
+    }
+
+    fn move_ptr(&mut self, offset: isize) -> io::Result<()> {
+        let pointer = self.temporary();
+        let moved = self.temporary();
+        writeln!(self.output, "  {pointer} = load i64, ptr %pointer")?;
+        writeln!(self.output, "  {moved} = add i64 {pointer}, {offset}")?;
+        writeln!(self.output, "  store i64 {moved}, ptr %pointer")
+    }
+
+    fn transpile(&mut self, expressions: &[Expression]) -> io::Result<()> {
+        for expression in expressions {
+            match expression {
+                Expression::IncVal(amount) => {
+                    let address = self.address(0)?;
+                    self.update(&address, "add", &amount.to_string())?;
+                }
+                Expression::DecVal(amount) => {
+                    let address = self.address(0)?;
+                    self.update(&address, "sub", &amount.to_string())?;
+                }
+                Expression::IncPtr(amount) => self.move_ptr(*amount as isize)?,
+                Expression::DecPtr(amount) => self.move_ptr(-(*amount as isize))?,
+                Expression::MulVal(offset, amount) => {
+                    let value = self.address(0)?;
+                    let value = self.load(&value)?;
+
+                    // skipped for an empty cell, whose target may lie off the tape
+                    self.next += 1;
+                    let label = self.next;
+                    let condition = self.temporary();
+                    writeln!(
+                        self.output,
+                        "  {condition} = icmp ne {} {value}, 0",
+                        self.cell
+                    )?;
+                    writeln!(
+                        self.output,
+                        "  br i1 {condition}, label %mul{label}, label %skip{label}\n\nmul{label}:"
+                    )?;
+
+                    let product = self.temporary();
+                    writeln!(
+                        self.output,
+                        "  {product} = mul {} {value}, {amount}",
+                        self.cell
+                    )?;
+
+                    let target = self.address(*offset)?;
+                    self.update(&target, "add", &product)?;
+                    writeln!(self.output, "  br label %skip{label}\n\nskip{label}:")?;
+                }
+                Expression::Clear => {
+                    let address = self.address(0)?;
+                    writeln!(self.output, "  store {} 0, ptr {address}", self.cell)?;
+                }
+                Expression::Loop(body) => {
+                    self.next += 1;
+                    let label = self.next;
+                    writeln!(self.output, "  br label %loop{label}\n\nloop{label}:")?;
+
+                    let address = self.address(0)?;
+                    let value = self.load(&address)?;
+                    let condition = self.temporary();
+                    writeln!(
+                        self.output,
+                        "  {condition} = icmp ne {} {value}, 0",
+                        self.cell
+                    )?;
+                    writeln!(
+                        self.output,
+                        "  br i1 {condition}, label %body{label}, label %end{label}\n\nbody{label}:"
+                    )?;
+
+                    self.transpile(body)?;
+                    writeln!(self.output, "  br label %loop{label}\n\nend{label}:")?;
+                }
+                Expression::Output => {
+                    let address = self.address(0)?;
+                    let value = self.load(&address)?;
+                    let byte = match self.cell.clone().as_str() {
+                        "i8" => value,
+                        cell => {
+                            let byte = self.temporary();
+                            writeln!(self.output, "  {byte} = trunc {cell} {value} to i8")?;
+                            byte
+                        }
+                    };
+                    let character = self.temporary();
+                    writeln!(self.output, "  {character} = zext i8 {byte} to i32")?;
+                    writeln!(self.output, "  call i32 @putchar(i32 {character})")?;
+                }
+                Expression::Input => {
+                    let address = self.address(0)?;
+                    let character = self.temporary();
+                    let end = self.temporary();
+                    writeln!(self.output, "  {character} = call i32 @getchar()")?;
+                    writeln!(self.output, "  {end} = icmp eq i32 {character}, -1")?;
+
+                    let value = match self.cell.clone().as_str() {
+                        "i32" => character,
+                        cell => {
+                            let value = self.temporary();
+                            writeln!(self.output, "  {value} = trunc i32 {character} to {cell}")?;
+                            value
+                        }
+                    };
+                    let eof = match self.eof {
+                        Eof::Zero => "0".to_string(),
+                        Eof::Unchanged => self.load(&address)?,
+                        Eof::MinusOne => "-1".to_string(),
+                    };
+                    let result = self.temporary();
+                    writeln!(
+                        self.output,
+                        "  {result} = select i1 {end}, {cell} {eof}, {cell} {value}",
+                        cell = self.cell
+                    )?;
+                    writeln!(self.output, "  store {} {result}, ptr {address}", self.cell)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        "llvm"
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: CellWidth::ALL,
+            tape_sizes: 1..=usize::MAX,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Emitter for Transpiler {
+    fn extension(&self, _config: &Config) -> &'static str {
+        "ll"
+    }
+
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        _source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+        if config.line_directives || config.tape != Tape::Unchecked {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "llvm supports neither #line directives nor checked or growing tapes",
+            ));
+        }
+
+        let cell = format!("i{}", config.cell_width);
+        let memory_length = config.tape_size.to_string();
+        let template = config.runtime.as_deref().unwrap_or(RUNTIME);
+        let values = [("CELL", cell.as_str()), ("MEMORY_LENGTH", &memory_length)];
+        validate_template(template, &values.map(|(name, _)| name), &[])?;
+
+        write_template(output, template, &values, &mut |output| {
+            Generator {
+                output,
+                cell: cell.clone(),
+                eof: config.eof,
+                next: 0,
+            }
+            .transpile(expressions)
+        })?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use crate::backends::{
+        Config, Emitter, Tape,
+        transpilers::{llvm::Transpiler, toolchain},
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+
+    fn run(name: &str, text: &str, config: Config, input: &[u8]) -> Option<Vec<u8>> {
+        if !toolchain::available(&["lli"]) {
+            return None;
+        }
+        let module = toolchain::path("llvm", &format!("{name}.ll"));
+
+        let mut code = vec![];
+        Transpiler
+            .emit(&Pipeline::execute(text), None, &config, &mut code)
+            .unwrap();
+        std::fs::write(&module, code).unwrap();
+
+        // opaque pointers are only the default from llvm 15 on
+        let version = Command::new("lli")
+            .arg("--version")
+            .output()
+            .unwrap()
+            .stdout;
+        let major = String::from_utf8(version)
+            .unwrap()
+            .split("version ")
+            .nth(1)
+            .and_then(|version| version.split('.').next()?.parse::<u32>().ok())
+            .unwrap();
+
+        let mut command = Command::new("lli");
+        if major < 15 {
+            command.arg("-opaque-pointers");
+        }
+        let output = toolchain::run(command.arg(&module), input);
+        assert!(output.status.success());
+        Some(output.stdout)
+    }
+
+    #[test]
+    fn cell_widths() {
+        toolchain::cell_widths(&run);
+    }
+
+    #[test]
+    fn eofs() {
+        toolchain::eofs(&run);
+    }
+
+    #[test]
+    fn multiply() {
+        let text = "++++++++[>++++++++<-]>+.[-]<++[>+++<-]>[<++++++++>-]<.";
+        if let Some(output) = run("multiply", text, Config::default(), b"") {
+            assert_eq!(output, b"A0");
+        }
+    }
+
+    #[test]
+    fn empty_mul_val() {
+        // the loop never runs, so its target left of the tape is never touched
+        let text = format!("[-<+>]{}.", "+".repeat(65));
+        if let Some(output) = run("empty_mul_val", &text, Config::default(), b"") {
+            assert_eq!(output, b"A");
+        }
+    }
+
+    #[test]
+    fn unsupported_tape() {
+        let config = Config {
+            tape: Tape::Checked,
+            ..Config::default()
+        };
+
+        let error = Transpiler
+            .emit(&Pipeline::execute("+."), None, &config, &mut vec![])
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{self, Write};
+
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Mapping, Source, Tape,
+        transpilers::{Layout, code_line, validate_template, write_plain, write_template},
+    },
+    core::ir::Expression,
+};
+
+const RUNTIME: &str = include_str!("../../runtimes/runtime.py");
+
+/// Emits Python 3 with one `while` per loop of the program, in the plain style whatever
+/// the [`Config::style`]. A [`Config::runtime`] template can use `<TAPE>` for the new tape,
+/// a `bytearray` for 8-bit cells and a list otherwise, and `<MEMORY_LENGTH>` for its size,
+/// must define `tape`, `p`, `stdin` and `stdout` like the built-in one and hold `<CODE>`
+/// once.
+pub struct Transpiler;
+
+impl Transpiler {
+    fn statement(expression: &Expression, cells: u64, eof: &str) -> String {
+        let at = |offset: isize| match offset {
+            0 => "tape[p]".to_string(),
+            offset if offset < 0 => format!("tape[p - {}]", -offset),
+            offset => format!("tape[p + {offset}]"),
+        };
+
+        match expression {
+            Expression::IncVal(amount) => format!("tape[p] = (tape[p] + {amount}) % {cells}"),
+            Expression::DecVal(amount) => format!("tape[p] = (tape[p] - {amount}) % {cells}"),
+            Expression::IncPtr(amount) => format!("p += {amount}"),
+            Expression::DecPtr(amount) => format!("p -= {amount}"),
+            Expression::MulVal(offset, amount) => {
+                let cell = at(*offset);
+                format!("{cell} = ({cell} + tape[p] * {amount}) % {cells}")
+            }
+            Expression::Clear => "tape[p] = 0".to_string(),
+            Expression::Output if cells == 256 => "stdout.write(bytes([tape[p]]))".to_string(),
+            Expression::Output => "stdout.write(bytes([tape[p] % 256]))".to_string(),
+            Expression::Input => {
+                format!("byte = stdin.read(1)\ntape[p] = byte[0] if byte else {eof}")
+            }
+            Expression::Loop(_) => unreachable!("loops are written as blocks"),
+        }
+    }
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        "python"
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: CellWidth::ALL,
+            tape_sizes: 1..=usize::MAX,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Emitter for Transpiler {
+    fn extension(&self, _config: &Config) -> &'static str {
+        "py"
+    }
+
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+        if config.line_directives || config.tape != Tape::Unchecked {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "python supports neither #line directives nor checked or growing tapes",
+            ));
+        }
+
+        let cells = 1u64 << config.cell_width.bits();
+        let eof = match config.eof {
+            Eof::Zero => "0".to_string(),
+            Eof::Unchanged => "tape[p]".to_string(),
+            Eof::MinusOne => (cells - 1).to_string(),
+        };
+        let memory_length = config.tape_size.to_string();
+        let tape = match config.cell_width {
+            CellWidth::U8 => format!("bytearray({memory_length})"),
+            _ => format!("[0] * {memory_length}"),
+        };
+        let template = config.runtime.as_deref().unwrap_or(RUNTIME);
+        let values = [("TAPE", tape.as_str()), ("MEMORY_LENGTH", &memory_length)];
+        validate_template(
+            template,
+            &values.map(|(name, _)| name),
+            &["tape", "p", "stdin", "stdout"],
+        )?;
+
+        let layout = Layout {
+            open: "while tape[p]:",
+            close: None,
+            comment: "#",
+            statement: &|expression| Self::statement(expression, cells, &eof),
+            comments: config.comments,
+            directives: false,
+            line: code_line(template),
+        };
+
+        let mut mappings = vec![];
+        write_template(output, template, &values, &mut |output| {
+            mappings = write_plain(output, expressions, source, &layout)?;
+            Ok(())
+        })?;
+        Ok(mappings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use crate::backends::{
+        Config, Emitter,
+        transpilers::{python::Transpiler, toolchain},
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+
+    fn run(name: &str, text: &str, config: Config, input: &[u8]) -> Option<Vec<u8>> {
+        if !toolchain::available(&["python3"]) {
+            return None;
+        }
+        let script = toolchain::path("python", &format!("{name}.py"));
+
+        let mut code = vec![];
+        Transpiler
+            .emit(&Pipeline::execute(text), None, &config, &mut code)
+            .unwrap();
+        std::fs::write(&script, code).unwrap();
+
+        let output = toolchain::run(Command::new("python3").arg(&script), input);
+        assert!(output.status.success());
+        Some(output.stdout)
+    }
+
+    #[test]
+    fn cell_widths() {
+        toolchain::cell_widths(&run);
+    }
+
+    #[test]
+    fn eofs() {
+        toolchain::eofs(&run);
+    }
+
+    #[test]
+    fn nesting() {
+        let mut code = vec![];
+        Transpiler
+            .emit(
+                &Pipeline::execute("+[,[.,]]"),
+                None,
+                &Config::default(),
+                &mut code,
+            )
+            .unwrap();
+
+        let code = String::from_utf8(code).unwrap();
+        assert!(code.contains(
+            "    while tape[p]:\n\
+             \x20       byte = stdin.read(1)\n\
+             \x20       tape[p] = byte[0] if byte else 0\n\
+             \x20       while tape[p]:\n\
+             \x20           stdout.write(bytes([tape[p]]))\n"
+        ));
+        if let Some(output) = run("echo", "+[,[.,]]", Config::default(), b"echo") {
+            assert_eq!(output, b"echo");
+        }
+    }
+}
@@ -1,77 +1,461 @@
-use crate::{backends, core::ir::Expression};
+use std::io::{self, Write};
 
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Mapping, Source, Style, Tape,
+        transpilers::{Layout, code_line, render, validate_template, write_plain, write_template},
+    },
+    core::{ir::Expression, pipeline::Pipeline},
+};
+
+/// Emits Rust. A [`Config::runtime`] template can use `<CELL>` for the cell type,
+/// `<MEMORY_LENGTH>` for the tape size and `<TAPE>` for the functions the [`Tape`] needs,
+/// plus `<EOF>` for what `,` stores at the end of input and `<POINTER>` and `<MEMORY>`
+/// for the names the macros use in the macro style, and must hold `<CODE>` once. With a
+/// [`Config::function`] it writes plain statements into a function, whose template has
+/// `<NAME>` instead of `<TAPE>`. Plain statements need `tape`, `p`, the byte iterator
+/// `input` and the writer `output` in scope like the built-in templates, and the macro
+/// style needs every one of the [`MACROS`].
 pub struct Transpiler;
 
+/// Names the statements of the plain style and of a function use.
+const PLAIN_NAMES: &[&str] = &["tape", "p", "input", "output"];
+
+/// The macros the code of the macro style is written in.
+const MACROS: &[&str] = &[
+    "inc_val_by",
+    "dec_val_by",
+    "inc_ptr_by",
+    "dec_ptr_by",
+    "mul_val_by",
+    "r#loop",
+    "clear",
+    "output",
+    "input",
+];
+
 const POINTER: &str = "pointer";
 const MEMORY: &str = "memory";
 
 const RUNTIME: &str = include_str!("../../runtimes/runtime.rs");
+const PLAIN_RUNTIME: &str = include_str!("../../runtimes/plain.rs");
+const FUNCTION_RUNTIME: &str = include_str!("../../runtimes/function.rs");
 
-impl Transpiler {
-    fn do_transpile(depth: usize, expressions: &[Expression]) -> String {
-        let mut buffer = String::with_capacity(1024);
+/// Words that cannot name a function, strict or reserved in any edition, plus `_`.
+const KEYWORDS: &[&str] = &[
+    "_", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if",
+    "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+const UNCHECKED: &str = include_str!("../../runtimes/tapes/unchecked.rs");
+const CHECKED: &str = include_str!("../../runtimes/tapes/checked.rs");
+const GROWING: &str = include_str!("../../runtimes/tapes/growing.rs");
 
-        for expression in expressions {
+impl Transpiler {
+    fn do_transpile(
+        output: &mut dyn Write,
+        depth: usize,
+        expressions: &[Expression],
+    ) -> io::Result<()> {
+        for (index, expression) in expressions.iter().enumerate() {
             for _ in 0..depth {
-                buffer.push('\t')
+                output.write_all(b"\t")?;
             }
 
             match expression {
                 Expression::IncVal(amount) => {
-                    buffer.push_str(&format!("inc_val_by!({MEMORY}, {POINTER}, {amount})"));
+                    write!(output, "inc_val_by!({MEMORY}, {POINTER}, {amount})")?;
                 }
                 Expression::DecVal(amount) => {
-                    buffer.push_str(&format!("dec_val_by!({MEMORY}, {POINTER}, {amount})"));
+                    write!(output, "dec_val_by!({MEMORY}, {POINTER}, {amount})")?;
                 }
                 Expression::IncPtr(amount) => {
-                    buffer.push_str(&format!("inc_ptr_by!({POINTER}, {amount})"));
+                    write!(output, "inc_ptr_by!({MEMORY}, {POINTER}, {amount})")?;
                 }
                 Expression::DecPtr(amount) => {
-                    buffer.push_str(&format!("dec_ptr_by!({POINTER}, {amount})"));
+                    write!(output, "dec_ptr_by!({MEMORY}, {POINTER}, {amount})")?;
                 }
                 Expression::Loop(expression) => {
-                    buffer.push_str(&format!("r#loop!({MEMORY}, {POINTER},\n"));
-                    buffer.push_str(&Self::do_transpile(depth + 1, expression));
-
-                    buffer.remove(buffer.len() - 1);
-                    buffer.remove(buffer.len() - 1);
-                    buffer.push('\n');
+                    writeln!(output, "r#loop!({MEMORY}, {POINTER},")?;
+                    Self::do_transpile(output, depth + 1, expression)?;
 
                     for _ in 0..depth {
-                        buffer.push('\t');
+                        output.write_all(b"\t")?;
                     }
-                    buffer.push(')');
+                    output.write_all(b")")?;
                 }
                 Expression::Output => {
-                    buffer.push_str(&format!("output!({MEMORY}, {POINTER})"));
+                    write!(output, "output!({MEMORY}, {POINTER})")?;
+                }
+                Expression::Input => {
+                    write!(output, "input!({MEMORY}, {POINTER})")?;
                 }
-                Expression::Input => {}
                 Expression::Clear => {
-                    buffer.push_str(&format!("clear!({MEMORY}, {POINTER})"));
+                    write!(output, "clear!({MEMORY}, {POINTER})")?;
                 }
                 Expression::MulVal(offset, amount) => {
-                    buffer.push_str(&format!(
+                    write!(
+                        output,
                         "mul_val_by!({MEMORY}, {POINTER}, {offset}, {amount})"
-                    ));
+                    )?;
                 }
             }
 
+            // loop bodies are macro arguments, separated by commas but not ended by one
             let text = match depth {
                 1 => ";\n",
+                _ if index + 1 == expressions.len() => "\n",
                 _ => ",\n",
             };
-            buffer.push_str(text);
+            output.write_all(text.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// A function hands I/O errors to its caller where a program unwraps them.
+    fn statement(
+        expression: &Expression,
+        tape: Tape,
+        cell: &str,
+        eof: &str,
+        function: bool,
+    ) -> String {
+        let at = |offset: isize| match offset {
+            0 => "tape[p]".to_string(),
+            offset if offset < 0 => format!("tape[p - {}]", -offset),
+            offset => format!("tape[p + {offset}]"),
+        };
+
+        match expression {
+            Expression::IncVal(amount) => format!("tape[p] = tape[p].wrapping_add({amount});"),
+            Expression::DecVal(amount) => format!("tape[p] = tape[p].wrapping_sub({amount});"),
+            Expression::IncPtr(amount) if tape == Tape::Unchecked => format!("p += {amount};"),
+            Expression::DecPtr(amount) if tape == Tape::Unchecked => format!("p -= {amount};"),
+            Expression::IncPtr(amount) => format!("p = shift(&mut tape, p, {amount});"),
+            Expression::DecPtr(amount) => format!("p = shift(&mut tape, p, -{amount});"),
+            Expression::MulVal(offset, amount) => {
+                let cell = at(*offset);
+                let add = format!("{cell} = {cell}.wrapping_add(tape[p].wrapping_mul({amount}));");
+                match tape {
+                    Tape::Unchecked => add,
+                    _ => format!(
+                        "if tape[p] != 0 {{\n    reach(&mut tape, p, {offset});\n    {add}\n}}"
+                    ),
+                }
+            }
+            Expression::Clear => "tape[p] = 0;".to_string(),
+            Expression::Output if function => "output.write_all(&[tape[p] as u8])?;".to_string(),
+            Expression::Output => "output.write_all(&[tape[p] as u8]).unwrap();".to_string(),
+            Expression::Input if function => format!(
+                "tape[p] = match input.next() {{ Some(byte) => byte? as {cell}, None => {eof} }};"
+            ),
+            Expression::Input => format!(
+                "tape[p] = match input.next() {{ Some(Ok(byte)) => byte as {cell}, _ => {eof} }};"
+            ),
+            Expression::Loop(_) => unreachable!("loops are written as blocks"),
+        }
+    }
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: CellWidth::ALL,
+            tape_sizes: 1..=usize::MAX,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Emitter for Transpiler {
+    fn extension(&self, _config: &Config) -> &'static str {
+        "rs"
+    }
+
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+        if config.line_directives {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "rust does not support #line directives",
+            ));
+        }
+
+        let cell = format!("u{}", config.cell_width);
+        let memory_length = config.tape_size.to_string();
+
+        if let Some(name) = &config.function {
+            let identifier = name
+                .starts_with(|char: char| char.is_ascii_alphabetic() || char == '_')
+                && name
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '_');
+            if !identifier || KEYWORDS.contains(&name.as_str()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("`{name}` is not a rust function name"),
+                ));
+            }
+            // the tape functions would clash between functions included into one module,
+            // and end the whole process
+            if config.tape != Tape::Unchecked {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "a rust function only supports the unchecked tape, which panics off its end",
+                ));
+            }
+        }
+
+        if config.style == Style::Plain || config.function.is_some() {
+            let eof = match config.eof {
+                Eof::Zero => "0",
+                Eof::Unchanged => "tape[p]",
+                Eof::MinusOne => "!0",
+            };
+            let tape = match config.tape {
+                Tape::Unchecked => String::new(),
+                Tape::Checked => format!("\n{}\n", render(CHECKED, &[("CELL", &cell)])),
+                Tape::Growing => format!("\n{}\n", render(GROWING, &[("CELL", &cell)])),
+            };
+            let (template, values) = match &config.function {
+                Some(name) => (FUNCTION_RUNTIME, ("NAME", name.as_str())),
+                None => (PLAIN_RUNTIME, ("TAPE", tape.as_str())),
+            };
+            let template = config.runtime.as_deref().unwrap_or(template);
+            let values = [
+                ("CELL", cell.as_str()),
+                ("MEMORY_LENGTH", &memory_length),
+                values,
+            ];
+            validate_template(template, &values.map(|(name, _)| name), PLAIN_NAMES)?;
+
+            let function = config.function.is_some();
+            let layout = Layout {
+                open: "while tape[p] != 0 {",
+                close: Some("}"),
+                comment: "//",
+                statement: &|expression| {
+                    Self::statement(expression, config.tape, &cell, eof, function)
+                },
+                comments: config.comments,
+                directives: false,
+                line: code_line(template),
+            };
+
+            let mut mappings = vec![];
+            write_template(output, template, &values, &mut |output| {
+                mappings = write_plain(output, expressions, source, &layout)?;
+                Ok(())
+            })?;
+            return Ok(mappings);
         }
-        buffer
+
+        let eof = match config.eof {
+            Eof::Zero => "0",
+            Eof::Unchanged => "$memory[$pointer]",
+            Eof::MinusOne => "!0",
+        };
+        let tape = match config.tape {
+            Tape::Unchecked => UNCHECKED,
+            Tape::Checked => CHECKED,
+            Tape::Growing => GROWING,
+        };
+        let tape = render(tape, &[("CELL", &cell)]);
+        let template = config.runtime.as_deref().unwrap_or(RUNTIME);
+        let values = [
+            ("POINTER", POINTER),
+            ("MEMORY", MEMORY),
+            ("CELL", &cell),
+            ("MEMORY_LENGTH", &memory_length),
+            ("TAPE", &tape),
+            ("EOF", eof),
+        ];
+        validate_template(template, &values.map(|(name, _)| name), MACROS)?;
+
+        write_template(output, template, &values, &mut |output| {
+            Self::do_transpile(output, 1, expressions)
+        })?;
+        Ok(vec![])
     }
 }
 
-impl backends::transpilers::Transpiler for Transpiler {
-    fn transpile(expressions: &[Expression]) -> String {
-        let code = Self::do_transpile(1, expressions);
-        RUNTIME
-            .replace("<POINTER>", POINTER)
-            .replace("<MEMORY>", MEMORY)
-            .replace("<CODE>", &code)
+/// Brainfuck `text` as `pub fn name(input: &mut impl Read, output: &mut impl Write) ->
+/// io::Result<()>`, for a `build.rs` to write out and a crate to `include!`.
+pub fn function(text: &str, name: &str, config: &Config) -> io::Result<String> {
+    let config = Config {
+        function: Some(name.to_string()),
+        ..config.clone()
+    };
+    let mut code = vec![];
+    Transpiler.emit(&Pipeline::execute(text), None, &config, &mut code)?;
+
+    Ok(String::from_utf8(code).expect("rust is text"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::{Command, Output};
+
+    use crate::backends::{
+        CellWidth, Config, Emitter, Eof, Style, Tape,
+        transpilers::{
+            rust::{Transpiler, function},
+            toolchain,
+        },
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn run(name: &str, text: &str, config: Config) -> Option<Output> {
+        if !toolchain::available(&["rustc"]) {
+            return None;
+        }
+        let (source, binary) = (
+            toolchain::path("rust", &format!("{name}.rs")),
+            toolchain::path("rust", name),
+        );
+
+        let mut code = vec![];
+        Transpiler
+            .emit(&Pipeline::execute(text), None, &config, &mut code)
+            .unwrap();
+        std::fs::write(&source, code).unwrap();
+        toolchain::build("rustc", &["-A", "warnings"], &source, &binary);
+
+        Some(toolchain::run(&mut Command::new(&binary), b""))
+    }
+
+    #[test_case(Style::Macros)]
+    #[test_case(Style::Plain)]
+    fn wide_cells_keep_input_on_eof(style: Style) {
+        let config = Config {
+            cell_width: CellWidth::U16,
+            tape_size: 10,
+            eof: Eof::Unchanged,
+            style,
+            ..Config::default()
+        };
+        let text =
+            ">>+++++++++++++++++++++++++++++++++<<++++++++++++++++[>++++++++++++++++<-]>,[>.<[-]]";
+
+        if let Some(output) = run(&format!("eof-{style}"), text, config) {
+            assert_eq!(output.stdout, b"!");
+        }
+    }
+
+    #[test_case(Style::Macros)]
+    #[test_case(Style::Plain)]
+    fn high_bytes(style: Style) {
+        let config = Config {
+            style,
+            ..Config::default()
+        };
+
+        let text = format!("{}.", "-".repeat(56));
+        if let Some(output) = run(&format!("high-{style}"), &text, config) {
+            assert_eq!(output.stdout, [200]);
+        }
+    }
+
+    #[test_case(
+        Tape::Checked,
+        Style::Macros,
+        "",
+        "cell 12, outside the tape of 10 cells"
+    )]
+    #[test_case(
+        Tape::Checked,
+        Style::Plain,
+        "",
+        "cell 12, outside the tape of 10 cells"
+    )]
+    #[test_case(Tape::Growing, Style::Macros, "!", "")]
+    #[test_case(Tape::Growing, Style::Plain, "!", "")]
+    fn tape(tape: Tape, style: Style, expected: &str, error: &str) {
+        let config = Config {
+            tape_size: 10,
+            tape,
+            style,
+            ..Config::default()
+        };
+        let text = ">>>>>>>>>>>>+++++++++++++++++++++++++++++++++[<<+>>-]<<.";
+
+        let Some(output) = run(&format!("tape-{tape}-{style}"), text, config) else {
+            return;
+        };
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+        assert!(String::from_utf8(output.stderr).unwrap().contains(error));
+        assert_eq!(output.status.success(), error.is_empty());
+    }
+
+    #[test]
+    fn functions_share_a_crate() {
+        if !toolchain::available(&["rustc"]) {
+            return;
+        }
+        let config = Config {
+            cell_width: CellWidth::U16,
+            ..Config::default()
+        };
+        for (name, text) in [
+            ("echo", ",[.,]"),
+            ("shout", ",[--------------------------------.,]"),
+        ] {
+            let code = function(text, name, &config).unwrap();
+            std::fs::write(toolchain::path("rust", &format!("{name}.rs")), code).unwrap();
+        }
+        let (source, binary) = (
+            toolchain::path("rust", "functions.rs"),
+            toolchain::path("rust", "functions"),
+        );
+        std::fs::write(
+            &source,
+            "include!(\"echo.rs\");\n\
+             include!(\"shout.rs\");\n\
+             fn main() {\n\
+             \x20   let mut output = vec![];\n\
+             \x20   echo(&mut &b\"hi\"[..], &mut output).unwrap();\n\
+             \x20   shout(&mut &b\"hi\"[..], &mut output).unwrap();\n\
+             \x20   print!(\"{}\", String::from_utf8(output).unwrap());\n\
+             }\n",
+        )
+        .unwrap();
+
+        toolchain::build("rustc", &[], &source, &binary);
+        assert_eq!(Command::new(&binary).output().unwrap().stdout, b"hiHI");
+    }
+
+    #[test_case("1st", Tape::Unchecked)]
+    #[test_case("run-bf", Tape::Unchecked)]
+    #[test_case("_", Tape::Unchecked)]
+    #[test_case("fn", Tape::Unchecked)]
+    #[test_case("loop", Tape::Unchecked)]
+    #[test_case("match", Tape::Unchecked)]
+    #[test_case("self", Tape::Unchecked)]
+    #[test_case("type", Tape::Unchecked)]
+    #[test_case("run", Tape::Checked)]
+    fn invalid_functions(name: &str, tape: Tape) {
+        let config = Config {
+            tape,
+            ..Config::default()
+        };
+
+        let error = function("+.", name, &config).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
     }
 }
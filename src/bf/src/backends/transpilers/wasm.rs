@@ -0,0 +1,754 @@
+use std::io::{self, Write};
+
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Format, Mapping, Source, Tape,
+    },
+    core::ir::Expression,
+};
+
+/// Bytes before the tape, where the WASI flavour keeps its iovec, byte and count.
+const SCRATCH: i32 = 16;
+const IOVEC: i32 = 0;
+const BYTE: i32 = 8;
+const COUNT: i32 = 12;
+
+const PAGE: usize = 65_536;
+
+/// Where the program gets its bytes from and sends them to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flavour {
+    /// Imports `env.putchar(i32)` and `env.getchar() -> i32`, which returns -1 at the end of
+    /// input, and exports `run`; for browsers and other hosts that bring their own I/O.
+    Imports,
+    /// Reads stdin and writes stdout with `fd_read` and `fd_write` and exports `_start`.
+    Wasi,
+}
+
+/// Encodes a WebAssembly module without any external tools, or its text form with
+/// [`Format::Wat`]. The tape sits in the exported `memory` after [`SCRATCH`] bytes, and the
+/// pointer is a local holding the byte address of the current cell.
+pub struct Transpiler {
+    pub flavour: Flavour,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Instruction {
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    Const(i32),
+    Add,
+    Sub,
+    Mul,
+    Eq,
+    Eqz,
+    And,
+    /// Loads a cell of this many bytes, zero extended.
+    Load(u32),
+    /// Stores the low bytes of a value into a cell of this many bytes.
+    Store(u32),
+    Block,
+    Loop,
+    /// An `if` that leaves an `i32` behind.
+    If,
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Call(u32),
+    Drop,
+}
+
+impl Instruction {
+    fn encode(&self, output: &mut Vec<u8>) {
+        // alignment hint and offset of a memory access
+        let memory = |output: &mut Vec<u8>, opcode: u8, bytes: u32| {
+            output.push(opcode);
+            unsigned(output, bytes.trailing_zeros());
+            unsigned(output, 0);
+        };
+
+        match *self {
+            Instruction::LocalGet(index) => index_of(output, 0x20, index),
+            Instruction::LocalSet(index) => index_of(output, 0x21, index),
+            Instruction::LocalTee(index) => index_of(output, 0x22, index),
+            Instruction::Const(value) => {
+                output.push(0x41);
+                signed(output, value);
+            }
+            Instruction::Add => output.push(0x6a),
+            Instruction::Sub => output.push(0x6b),
+            Instruction::Mul => output.push(0x6c),
+            Instruction::Eq => output.push(0x46),
+            Instruction::Eqz => output.push(0x45),
+            Instruction::And => output.push(0x71),
+            Instruction::Load(1) => memory(output, 0x2d, 1),
+            Instruction::Load(2) => memory(output, 0x2f, 2),
+            Instruction::Load(bytes) => memory(output, 0x28, bytes),
+            Instruction::Store(1) => memory(output, 0x3a, 1),
+            Instruction::Store(2) => memory(output, 0x3b, 2),
+            Instruction::Store(bytes) => memory(output, 0x36, bytes),
+            Instruction::Block => output.extend([0x02, 0x40]),
+            Instruction::Loop => output.extend([0x03, 0x40]),
+            Instruction::If => output.extend([0x04, 0x7f]),
+            Instruction::Else => output.push(0x05),
+            Instruction::End => output.push(0x0b),
+            Instruction::Br(depth) => index_of(output, 0x0c, depth),
+            Instruction::BrIf(depth) => index_of(output, 0x0d, depth),
+            Instruction::Call(function) => index_of(output, 0x10, function),
+            Instruction::Drop => output.push(0x1a),
+        }
+    }
+
+    fn text(&self, locals: &[&str], functions: &[&str]) -> String {
+        match *self {
+            Instruction::LocalGet(index) => format!("local.get ${}", locals[index as usize]),
+            Instruction::LocalSet(index) => format!("local.set ${}", locals[index as usize]),
+            Instruction::LocalTee(index) => format!("local.tee ${}", locals[index as usize]),
+            Instruction::Const(value) => format!("i32.const {value}"),
+            Instruction::Add => "i32.add".to_string(),
+            Instruction::Sub => "i32.sub".to_string(),
+            Instruction::Mul => "i32.mul".to_string(),
+            Instruction::Eq => "i32.eq".to_string(),
+            Instruction::Eqz => "i32.eqz".to_string(),
+            Instruction::And => "i32.and".to_string(),
+            Instruction::Load(1) => "i32.load8_u".to_string(),
+            Instruction::Load(2) => "i32.load16_u".to_string(),
+            Instruction::Load(_) => "i32.load".to_string(),
+            Instruction::Store(1) => "i32.store8".to_string(),
+            Instruction::Store(2) => "i32.store16".to_string(),
+            Instruction::Store(_) => "i32.store".to_string(),
+            Instruction::Block => "block".to_string(),
+            Instruction::Loop => "loop".to_string(),
+            Instruction::If => "if (result i32)".to_string(),
+            Instruction::Else => "else".to_string(),
+            Instruction::End => "end".to_string(),
+            Instruction::Br(depth) => format!("br {depth}"),
+            Instruction::BrIf(depth) => format!("br_if {depth}"),
+            Instruction::Call(function) => format!("call ${}", functions[function as usize]),
+            Instruction::Drop => "drop".to_string(),
+        }
+    }
+}
+
+fn unsigned(output: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        match value {
+            0 => return output.push(byte),
+            _ => output.push(byte | 0x80),
+        }
+    }
+}
+
+fn signed(output: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        match done {
+            true => return output.push(byte),
+            false => output.push(byte | 0x80),
+        }
+    }
+}
+
+fn index_of(output: &mut Vec<u8>, opcode: u8, index: u32) {
+    output.push(opcode);
+    unsigned(output, index);
+}
+
+fn name(output: &mut Vec<u8>, name: &str) {
+    unsigned(output, name.len() as u32);
+    output.extend(name.as_bytes());
+}
+
+/// A function defined in the module, its type being an index into [`TYPES`].
+struct Function {
+    name: &'static str,
+    r#type: u32,
+    locals: &'static [&'static str],
+    export: Option<&'static str>,
+    body: Vec<Instruction>,
+}
+
+/// `(i32)`, `() -> i32`, `()` and the `(i32, i32, i32, i32) -> i32` of `fd_read`/`fd_write`.
+const TYPES: &[(&[u8], &[u8])] = &[
+    (&[0x7f], &[]),
+    (&[], &[0x7f]),
+    (&[], &[]),
+    (&[0x7f; 4], &[0x7f]),
+];
+const TYPE_NAMES: &[&str] = &[
+    "(param i32)",
+    "(result i32)",
+    "",
+    "(param i32 i32 i32 i32) (result i32)",
+];
+
+/// The `(module, name, type)` of every import of a flavour.
+fn imports(flavour: Flavour) -> &'static [(&'static str, &'static str, u32)] {
+    match flavour {
+        Flavour::Imports => &[("env", "putchar", 0), ("env", "getchar", 1)],
+        Flavour::Wasi => &[
+            ("wasi_snapshot_preview1", "fd_write", 3),
+            ("wasi_snapshot_preview1", "fd_read", 3),
+        ],
+    }
+}
+
+struct Generator {
+    bytes: u32,
+    eof: Eof,
+    putchar: u32,
+    getchar: u32,
+    body: Vec<Instruction>,
+}
+
+impl Generator {
+    const POINTER: u32 = 0;
+    const TEMPORARY: u32 = 1;
+
+    fn emit(&mut self, instructions: &[Instruction]) {
+        self.body.extend_from_slice(instructions);
+    }
+
+    /// Leaves the byte address of the cell `offset` away from the pointer on the stack.
+    fn address(&mut self, offset: isize) {
+        self.emit(&[Instruction::LocalGet(Self::POINTER)]);
+        if offset != 0 {
+            self.emit(&[
+                Instruction::Const(offset as i32 * self.bytes as i32),
+                Instruction::Add,
+            ]);
+        }
+    }
+
+    fn update(&mut self, operation: Instruction, amount: u8) {
+        use Instruction::*;
+        self.emit(&[
+            LocalGet(Self::POINTER),
+            LocalGet(Self::POINTER),
+            Load(self.bytes),
+            Const(amount as i32),
+            operation,
+            Store(self.bytes),
+        ]);
+    }
+
+    fn transpile(&mut self, expressions: &[Expression]) {
+        use Instruction::*;
+
+        for expression in expressions {
+            match expression {
+                Expression::IncVal(amount) => self.update(Add, *amount),
+                Expression::DecVal(amount) => self.update(Sub, *amount),
+                Expression::IncPtr(amount) => {
+                    self.address(*amount as isize);
+                    self.emit(&[LocalSet(Self::POINTER)]);
+                }
+                Expression::DecPtr(amount) => {
+                    self.address(-(*amount as isize));
+                    self.emit(&[LocalSet(Self::POINTER)]);
+                }
+                Expression::MulVal(offset, amount) => {
+                    // skipped for an empty cell, whose target may lie off the tape
+                    self.emit(&[
+                        Block,
+                        LocalGet(Self::POINTER),
+                        Load(self.bytes),
+                        Eqz,
+                        BrIf(0),
+                    ]);
+                    self.address(*offset);
+                    self.emit(&[
+                        LocalTee(Self::TEMPORARY),
+                        LocalGet(Self::TEMPORARY),
+                        Load(self.bytes),
+                        LocalGet(Self::POINTER),
+                        Load(self.bytes),
+                        Const(*amount as i32),
+                        Mul,
+                        Add,
+                        Store(self.bytes),
+                        End,
+                    ]);
+                }
+                Expression::Clear => {
+                    self.emit(&[LocalGet(Self::POINTER), Const(0), Store(self.bytes)]);
+                }
+                Expression::Loop(body) => {
+                    self.emit(&[
+                        Block,
+                        Loop,
+                        LocalGet(Self::POINTER),
+                        Load(self.bytes),
+                        Eqz,
+                        BrIf(1),
+                    ]);
+                    self.transpile(body);
+                    self.emit(&[Br(0), End, End]);
+                }
+                Expression::Output => {
+                    self.emit(&[
+                        LocalGet(Self::POINTER),
+                        Load(self.bytes),
+                        Const(0xff),
+                        And,
+                        Call(self.putchar),
+                    ]);
+                }
+                Expression::Input => {
+                    self.emit(&[
+                        LocalGet(Self::POINTER),
+                        Call(self.getchar),
+                        LocalTee(Self::TEMPORARY),
+                        Const(-1),
+                        Eq,
+                        If,
+                    ]);
+                    match self.eof {
+                        Eof::Zero => self.emit(&[Const(0)]),
+                        Eof::Unchanged => self.emit(&[LocalGet(Self::POINTER), Load(self.bytes)]),
+                        Eof::MinusOne => self.emit(&[Const(-1)]),
+                    }
+                    self.emit(&[Else, LocalGet(Self::TEMPORARY), End, Store(self.bytes)]);
+                }
+            }
+        }
+    }
+}
+
+impl Transpiler {
+    /// `putchar` and `getchar` on top of the WASI calls, one byte at a time.
+    fn wasi_functions() -> Vec<Function> {
+        use Instruction::*;
+        let (fd_write, fd_read) = (0, 1);
+        let iovec = [
+            Const(IOVEC),
+            Const(BYTE),
+            Store(4),
+            Const(IOVEC + 4),
+            Const(1),
+            Store(4),
+        ];
+        let call = |fd: i32, function: u32| {
+            [
+                Const(fd),
+                Const(IOVEC),
+                Const(1),
+                Const(COUNT),
+                Call(function),
+                Drop,
+            ]
+        };
+
+        let mut putchar = vec![Const(BYTE), LocalGet(0), Store(1)];
+        putchar.extend(iovec);
+        putchar.extend(call(1, fd_write));
+
+        let mut getchar = iovec.to_vec();
+        getchar.extend(call(0, fd_read));
+        getchar.extend([
+            Const(COUNT),
+            Load(4),
+            Eqz,
+            If,
+            Const(-1),
+            Else,
+            Const(BYTE),
+            Load(1),
+            End,
+        ]);
+
+        vec![
+            Function {
+                name: "putchar",
+                r#type: 0,
+                locals: &["byte"],
+                export: None,
+                body: putchar,
+            },
+            Function {
+                name: "getchar",
+                r#type: 1,
+                locals: &[],
+                export: None,
+                body: getchar,
+            },
+        ]
+    }
+
+    fn functions(&self, expressions: &[Expression], config: &Config) -> Vec<Function> {
+        let mut functions = match self.flavour {
+            Flavour::Imports => vec![],
+            Flavour::Wasi => Self::wasi_functions(),
+        };
+
+        let defined = imports(self.flavour).len() as u32;
+        let (putchar, getchar) = match self.flavour {
+            Flavour::Imports => (0, 1),
+            Flavour::Wasi => (defined, defined + 1),
+        };
+        let mut generator = Generator {
+            bytes: config.cell_width.bits() / 8,
+            eof: config.eof,
+            putchar,
+            getchar,
+            body: vec![
+                Instruction::Const(SCRATCH),
+                Instruction::LocalSet(Generator::POINTER),
+            ],
+        };
+        generator.transpile(expressions);
+
+        let (name, export) = match self.flavour {
+            Flavour::Imports => ("run", "run"),
+            Flavour::Wasi => ("start", "_start"),
+        };
+        functions.push(Function {
+            name,
+            r#type: 2,
+            locals: &["pointer", "temporary"],
+            export: Some(export),
+            body: generator.body,
+        });
+        functions
+    }
+
+    fn write_wasm(
+        &self,
+        functions: &[Function],
+        pages: usize,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        let imports = imports(self.flavour);
+        let section = |output: &mut dyn Write, id: u8, count: usize, content: &[u8]| {
+            let mut header = vec![id];
+            let mut body = vec![];
+            unsigned(&mut body, count as u32);
+            body.extend(content);
+            unsigned(&mut header, body.len() as u32);
+            output.write_all(&header)?;
+            output.write_all(&body)
+        };
+
+        output.write_all(b"\0asm\x01\0\0\0")?;
+
+        let mut types = vec![];
+        for (params, results) in TYPES {
+            types.push(0x60);
+            for values in [params, results] {
+                unsigned(&mut types, values.len() as u32);
+                types.extend(*values);
+            }
+        }
+        section(output, 1, TYPES.len(), &types)?;
+
+        let mut content = vec![];
+        for (module, field, r#type) in imports {
+            name(&mut content, module);
+            name(&mut content, field);
+            content.push(0x00);
+            unsigned(&mut content, *r#type);
+        }
+        section(output, 2, imports.len(), &content)?;
+
+        let mut content = vec![];
+        for function in functions {
+            unsigned(&mut content, function.r#type);
+        }
+        section(output, 3, functions.len(), &content)?;
+
+        let mut content = vec![0x00];
+        unsigned(&mut content, pages as u32);
+        section(output, 5, 1, &content)?;
+
+        let mut content = vec![];
+        name(&mut content, "memory");
+        content.extend([0x02, 0x00]);
+        let exported = functions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, function)| Some((index, function.export?)))
+            .collect::<Vec<_>>();
+        for (index, export) in &exported {
+            name(&mut content, export);
+            content.push(0x00);
+            unsigned(&mut content, (imports.len() + index) as u32);
+        }
+        section(output, 7, 1 + exported.len(), &content)?;
+
+        let mut content = vec![];
+        for function in functions {
+            let mut body = vec![];
+            match function.locals.len() - TYPES[function.r#type as usize].0.len() {
+                0 => body.push(0x00),
+                count => {
+                    body.push(0x01);
+                    unsigned(&mut body, count as u32);
+                    body.push(0x7f);
+                }
+            }
+            for instruction in &function.body {
+                instruction.encode(&mut body);
+            }
+            body.push(0x0b);
+
+            unsigned(&mut content, body.len() as u32);
+            content.extend(body);
+        }
+        section(output, 10, functions.len(), &content)
+    }
+
+    fn write_wat(
+        &self,
+        functions: &[Function],
+        pages: usize,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        let imports = imports(self.flavour);
+        let names = imports
+            .iter()
+            .map(|(_, field, _)| *field)
+            .chain(functions.iter().map(|function| function.name))
+            .collect::<Vec<_>>();
+
+        writeln!(output, "(module")?;
+        for (module, field, r#type) in imports {
+            let signature = TYPE_NAMES[*r#type as usize];
+            writeln!(
+                output,
+                "  (import \"{module}\" \"{field}\" (func ${field} {signature}))"
+            )?;
+        }
+        writeln!(output, "  (memory (export \"memory\") {pages})")?;
+
+        for function in functions {
+            let parameters = TYPES[function.r#type as usize].0.len();
+            write!(output, "\n  (func ${}", function.name)?;
+            if let Some(export) = function.export {
+                write!(output, " (export \"{export}\")")?;
+            }
+            for (index, local) in function.locals.iter().enumerate() {
+                match index < parameters {
+                    true => write!(output, " (param ${local} i32)")?,
+                    false => write!(output, " (local ${local} i32)")?,
+                }
+            }
+            if !TYPES[function.r#type as usize].1.is_empty() {
+                write!(output, " (result i32)")?;
+            }
+            writeln!(output)?;
+
+            let mut depth = 2;
+            for instruction in &function.body {
+                if matches!(instruction, Instruction::Else | Instruction::End) {
+                    depth -= 1;
+                }
+                let text = instruction.text(function.locals, &names);
+                writeln!(output, "{:width$}{text}", "", width = depth * 2)?;
+                if matches!(
+                    instruction,
+                    Instruction::Block | Instruction::Loop | Instruction::If | Instruction::Else
+                ) {
+                    depth += 1;
+                }
+            }
+            writeln!(output, "  )")?;
+        }
+        writeln!(output, ")")
+    }
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        match self.flavour {
+            Flavour::Imports => "wasm",
+            Flavour::Wasi => "wasi",
+        }
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: CellWidth::ALL,
+            // so even 32-bit cells fit in the 4 GiB of a 32-bit memory
+            tape_sizes: 1..=1 << 29,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Emitter for Transpiler {
+    fn extension(&self, config: &Config) -> &'static str {
+        match config.format {
+            Format::Wasm => "wasm",
+            Format::Wat => "wat",
+        }
+    }
+
+    /// Unlike the other emitters this builds the module in memory, as every section of the
+    /// binary starts with its size.
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        _source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+        if config.line_directives || config.tape != Tape::Unchecked || config.runtime.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} supports neither #line directives, checked or growing tapes nor runtime templates",
+                    self.name()
+                ),
+            ));
+        }
+
+        let bytes = SCRATCH as usize + config.tape_size * (config.cell_width.bits() as usize / 8);
+        let pages = bytes.div_ceil(PAGE);
+        let functions = self.functions(expressions, config);
+
+        match config.format {
+            Format::Wasm => self.write_wasm(&functions, pages, output)?,
+            Format::Wat => self.write_wat(&functions, pages, output)?,
+        }
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use crate::backends::transpilers::{
+        toolchain,
+        wasm::{Flavour, Transpiler, signed},
+    };
+    use crate::backends::{Config, Emitter, Format};
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    const WASI: &str = "
+        const { WASI } = require('node:wasi');
+        const wasi = new WASI({ version: 'preview1' });
+        WebAssembly.instantiate(require('node:fs').readFileSync(process.argv[1]), wasi.getImportObject())
+            .then(({ instance }) => wasi.start(instance));";
+
+    const IMPORTS: &str = "
+        const input = [...require('node:fs').readFileSync(0)];
+        const output = [];
+        const env = { putchar: (byte) => output.push(byte), getchar: () => input.shift() ?? -1 };
+        WebAssembly.instantiate(require('node:fs').readFileSync(process.argv[1]), { env })
+            .then(({ instance }) => { instance.exports.run(); process.stdout.write(Buffer.from(output)); });";
+
+    fn run(
+        name: &str,
+        text: &str,
+        flavour: Flavour,
+        config: Config,
+        input: &[u8],
+    ) -> Option<Vec<u8>> {
+        if !toolchain::available(&["node"]) {
+            return None;
+        }
+        let module = toolchain::path("wasm", &format!("{name}-{flavour:?}.wasm"));
+
+        let mut code = vec![];
+        Transpiler { flavour }
+            .emit(&Pipeline::execute(text), None, &config, &mut code)
+            .unwrap();
+        std::fs::write(&module, code).unwrap();
+
+        let script = match flavour {
+            Flavour::Imports => IMPORTS,
+            Flavour::Wasi => WASI,
+        };
+        let output = toolchain::run(
+            Command::new("node")
+                .args(["--no-warnings", "-e", script])
+                .arg(&module),
+            input,
+        );
+        assert!(output.status.success());
+        Some(output.stdout)
+    }
+
+    #[test_case(0, &[0x00])]
+    #[test_case(63, &[0x3f])]
+    #[test_case(64, &[0xc0, 0x00])]
+    #[test_case(-1, &[0x7f])]
+    #[test_case(-65, &[0xbf, 0x7f])]
+    fn leb128(value: i32, expected: &[u8]) {
+        let mut output = vec![];
+        signed(&mut output, value);
+        assert_eq!(output, expected);
+    }
+
+    #[test_case(Flavour::Imports)]
+    #[test_case(Flavour::Wasi)]
+    fn cell_widths(flavour: Flavour) {
+        toolchain::cell_widths(&|name, text, config, input| {
+            run(name, text, flavour, config, input)
+        });
+    }
+
+    #[test_case(Flavour::Imports)]
+    #[test_case(Flavour::Wasi)]
+    fn eofs(flavour: Flavour) {
+        toolchain::eofs(&|name, text, config, input| run(name, text, flavour, config, input));
+    }
+
+    #[test]
+    fn wat() {
+        let config = Config {
+            format: Format::Wat,
+            ..Config::default()
+        };
+        let mut output = vec![];
+        Transpiler {
+            flavour: Flavour::Imports,
+        }
+        .emit(&Pipeline::execute("[<]."), None, &config, &mut output)
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"(module
+  (import "env" "putchar" (func $putchar (param i32)))
+  (import "env" "getchar" (func $getchar (result i32)))
+  (memory (export "memory") 1)
+
+  (func $run (export "run") (local $pointer i32) (local $temporary i32)
+    i32.const 16
+    local.set $pointer
+    block
+      loop
+        local.get $pointer
+        i32.load8_u
+        i32.eqz
+        br_if 1
+        local.get $pointer
+        i32.const -1
+        i32.add
+        local.set $pointer
+        br 0
+      end
+    end
+    local.get $pointer
+    i32.load8_u
+    i32.const 255
+    i32.and
+    call $putchar
+  )
+)
+"#
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::io::{self, Write};
+
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Emitter, Eof, Feature, Features, Mapping, Source, Tape,
+        transpilers::{validate_template, write_template},
+    },
+    core::ir::Expression,
+};
+
+const RUNTIME: &str = include_str!("../../runtimes/runtime.s");
+
+/// Emits x86-64 assembly for Linux in GNU `as` syntax, which talks to the kernel with
+/// `syscall` and links into a static binary without libc. A [`Config::runtime`] template
+/// can use `<MEMORY_LENGTH>` for the tape size and `<CELL_SIZE>` for the bytes per cell,
+/// must keep the pointer in `%rbx`, provide `putchar` and `getchar` like the built-in one
+/// and hold `<CODE>` once.
+pub struct Transpiler;
+
+struct Generator<'a> {
+    output: &'a mut dyn Write,
+    /// Operand size suffix of the cells.
+    suffix: char,
+    /// The part of `%eax` a cell fits in.
+    register: &'static str,
+    /// Loads a cell zero extended into `%eax`.
+    load: &'static str,
+    bytes: usize,
+    eof: Eof,
+    labels: usize,
+}
+
+impl Generator<'_> {
+    fn cell(&self, offset: isize) -> String {
+        match offset {
+            0 => "(%rbx)".to_string(),
+            offset => format!("{}(%rbx)", offset * self.bytes as isize),
+        }
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels
+    }
+
+    /// `[>]` and `[<]` and wider steps, as a compare and a move per cell.
+    fn scan(&mut self, step: isize) -> io::Result<()> {
+        let label = self.label();
+        let suffix = self.suffix;
+        writeln!(self.output, "    jmp .Lscan{label}")?;
+        writeln!(self.output, ".Lstep{label}:")?;
+        writeln!(self.output, "    add ${}, %rbx", step * self.bytes as isize)?;
+        writeln!(self.output, ".Lscan{label}:")?;
+        writeln!(self.output, "    cmp{suffix} $0, (%rbx)")?;
+        writeln!(self.output, "    jne .Lstep{label}")
+    }
+
+    fn transpile(&mut self, expressions: &[Expression]) -> io::Result<()> {
+        let suffix = self.suffix;
+
+        for expression in expressions {
+            match expression {
+                Expression::IncVal(amount) => {
+                    writeln!(self.output, "    add{suffix} ${amount}, (%rbx)")?;
+                }
+                Expression::DecVal(amount) => {
+                    writeln!(self.output, "    sub{suffix} ${amount}, (%rbx)")?;
+                }
+                Expression::IncPtr(amount) => {
+                    writeln!(self.output, "    add ${}, %rbx", amount * self.bytes)?;
+                }
+                Expression::DecPtr(amount) => {
+                    writeln!(self.output, "    sub ${}, %rbx", amount * self.bytes)?;
+                }
+                Expression::MulVal(offset, amount) => {
+                    // skipped for an empty cell, whose target may lie off the tape
+                    writeln!(self.output, "    {} (%rbx), %eax", self.load)?;
+                    writeln!(self.output, "    test %eax, %eax")?;
+                    writeln!(self.output, "    jz 1f")?;
+                    if *amount != 1 {
+                        writeln!(self.output, "    imul ${amount}, %eax, %eax")?;
+                    }
+                    writeln!(
+                        self.output,
+                        "    add {}, {}",
+                        self.register,
+                        self.cell(*offset)
+                    )?;
+                    writeln!(self.output, "1:")?;
+                }
+                Expression::Clear => writeln!(self.output, "    mov{suffix} $0, (%rbx)")?,
+                Expression::Loop(body) => match body.as_slice() {
+                    [Expression::IncPtr(step)] => self.scan(*step as isize)?,
+                    [Expression::DecPtr(step)] => self.scan(-(*step as isize))?,
+                    _ => {
+                        let label = self.label();
+                        writeln!(self.output, "    cmp{suffix} $0, (%rbx)")?;
+                        writeln!(self.output, "    je .Lend{label}")?;
+                        writeln!(self.output, ".Lbody{label}:")?;
+                        self.transpile(body)?;
+                        writeln!(self.output, "    cmp{suffix} $0, (%rbx)")?;
+                        writeln!(self.output, "    jne .Lbody{label}")?;
+                        writeln!(self.output, ".Lend{label}:")?;
+                    }
+                },
+                Expression::Output => {
+                    writeln!(self.output, "    {} (%rbx), %eax", self.load)?;
+                    writeln!(self.output, "    call putchar")?;
+                }
+                Expression::Input => {
+                    writeln!(self.output, "    call getchar")?;
+                    match self.eof {
+                        Eof::Zero => {
+                            writeln!(self.output, "    cmp $-1, %eax")?;
+                            writeln!(self.output, "    jne 1f")?;
+                            writeln!(self.output, "    xor %eax, %eax")?;
+                            writeln!(self.output, "1:")?;
+                            writeln!(self.output, "    mov {}, (%rbx)", self.register)?;
+                        }
+                        Eof::Unchanged => {
+                            writeln!(self.output, "    cmp $-1, %eax")?;
+                            writeln!(self.output, "    je 1f")?;
+                            writeln!(self.output, "    mov {}, (%rbx)", self.register)?;
+                            writeln!(self.output, "1:")?;
+                        }
+                        // the -1 getchar returns is already all ones
+                        Eof::MinusOne => {
+                            writeln!(self.output, "    mov {}, (%rbx)", self.register)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend for Transpiler {
+    fn name(&self) -> &'static str {
+        "x86_64"
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: CellWidth::ALL,
+            tape_sizes: 1..=usize::MAX,
+            eofs: Eof::ALL,
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Emitter for Transpiler {
+    fn extension(&self, _config: &Config) -> &'static str {
+        "s"
+    }
+
+    fn emit(
+        &self,
+        expressions: &[Expression],
+        _source: Option<&Source>,
+        config: &Config,
+        output: &mut dyn Write,
+    ) -> io::Result<Vec<Mapping>> {
+        self.check(expressions, config)?;
+        if config.line_directives || config.tape != Tape::Unchecked {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "x86_64 supports neither #line directives nor checked or growing tapes",
+            ));
+        }
+
+        let (suffix, register, load) = match config.cell_width {
+            CellWidth::U8 => ('b', "%al", "movzbl"),
+            CellWidth::U16 => ('w', "%ax", "movzwl"),
+            CellWidth::U32 => ('l', "%eax", "movl"),
+        };
+        let bytes = config.cell_width.bits() as usize / 8;
+        let (memory_length, cell_size) = (config.tape_size.to_string(), bytes.to_string());
+        let template = config.runtime.as_deref().unwrap_or(RUNTIME);
+        let values = [
+            ("MEMORY_LENGTH", memory_length.as_str()),
+            ("CELL_SIZE", &cell_size),
+        ];
+        validate_template(template, &values.map(|(name, _)| name), &[])?;
+
+        write_template(output, template, &values, &mut |output| {
+            Generator {
+                output,
+                suffix,
+                register,
+                load,
+                bytes,
+                eof: config.eof,
+                labels: 0,
+            }
+            .transpile(expressions)
+        })?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64", target_os = "linux"))]
+mod test {
+    use std::process::Command;
+
+    use crate::backends::{
+        Config, Emitter,
+        transpilers::{toolchain, x86_64::Transpiler},
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+
+    fn run(name: &str, text: &str, config: Config, input: &[u8]) -> Option<Vec<u8>> {
+        if !toolchain::available(&["as", "ld"]) {
+            return None;
+        }
+        let (source, object, binary) = (
+            toolchain::path("x86_64", &format!("{name}.s")),
+            toolchain::path("x86_64", &format!("{name}.o")),
+            toolchain::path("x86_64", name),
+        );
+
+        let mut code = vec![];
+        Transpiler
+            .emit(&Pipeline::execute(text), None, &config, &mut code)
+            .unwrap();
+        std::fs::write(&source, code).unwrap();
+        toolchain::build("as", &[], &source, &object);
+        toolchain::build("ld", &[], &object, &binary);
+
+        Some(toolchain::run(&mut Command::new(&binary), input).stdout)
+    }
+
+    #[test]
+    fn cell_widths() {
+        toolchain::cell_widths(&run);
+    }
+
+    #[test]
+    fn eofs() {
+        toolchain::eofs(&run);
+    }
+
+    #[test]
+    fn scan_and_multiply() {
+        let text = "+>>+>>+>>>++++++++[<++++++++>-]<+<<<<<<[>>]<<.";
+        let mut code = vec![];
+        Transpiler
+            .emit(
+                &Pipeline::execute(text),
+                None,
+                &Config::default(),
+                &mut code,
+            )
+            .unwrap();
+
+        assert!(String::from_utf8(code).unwrap().contains(".Lscan"));
+        if let Some(output) = run("scan", text, Config::default(), b"") {
+            assert_eq!(output, b"A");
+        }
+    }
+}
@@ -1,4 +1,99 @@
-use crate::core::ir::Expression;
+use std::io::{self, Read, Write};
+
+use crate::{
+    backends::{
+        Backend, CellWidth, Config, Eof, Executor, Feature, Features,
+        vm::{
+            bytecode::Bytecode,
+            journal::{CellWrite, Entry, Journal},
+            peephole::Peephole,
+            profiler::Profile,
+            snapshot::Snapshot,
+            threaded::Threaded,
+        },
+    },
+    core::ir::Expression,
+};
+
+pub mod bytecode;
+pub mod coverage;
+pub mod debugger;
+pub mod jit;
+pub mod journal;
+pub mod peephole;
+pub mod profiler;
+pub mod snapshot;
+pub mod threaded;
+
+/// Executes a compiled program; implemented by the [`Vm`] and the faster engines.
+pub trait Engine {
+    fn set_input(&mut self, input: Box<dyn Read>);
+    fn set_output(&mut self, output: Box<dyn Write>);
+    /// Runs to the end of the program, or returns the error that stopped it.
+    ///
+    /// A program runs once: later calls execute nothing and return the same result again.
+    fn run(&mut self) -> io::Result<()>;
+    fn pointer(&self) -> usize;
+    fn memory(&self) -> &[u8];
+}
+
+/// The engines as an [`Executor`], so they can be picked by name next to the emitters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EngineKind {
+    Vm,
+    Threaded,
+    Jit,
+}
+
+impl Backend for EngineKind {
+    fn name(&self) -> &'static str {
+        match self {
+            EngineKind::Vm => "vm",
+            EngineKind::Threaded => "threaded",
+            EngineKind::Jit => "jit",
+        }
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            cell_widths: &[CellWidth::U8],
+            tape_sizes: 30_000..=30_000,
+            eofs: &[Eof::Zero],
+            expressions: Feature::ALL,
+        }
+    }
+}
+
+impl Executor for EngineKind {
+    fn execute(
+        &self,
+        expressions: &[Expression],
+        config: &Config,
+        input: Box<dyn Read>,
+        output: Box<dyn Write>,
+    ) -> io::Result<()> {
+        self.check(expressions, config)?;
+
+        let mut engine = self.compile(expressions);
+        engine.set_input(input);
+        engine.set_output(output);
+        engine.run()
+    }
+}
+
+impl EngineKind {
+    pub fn compile(&self, expressions: &[Expression]) -> Box<dyn Engine> {
+        match self {
+            EngineKind::Vm => Box::new(Vm::from(&Peephole::optimize(&Interpreter::interpret(
+                expressions,
+            )))),
+            EngineKind::Threaded => Box::new(Threaded::from(&Peephole::optimize(
+                &Interpreter::interpret(expressions),
+            ))),
+            EngineKind::Jit => jit::compile(expressions),
+        }
+    }
+}
 
 pub struct Interpreter;
 
@@ -36,7 +131,9 @@ impl Interpreter {
                 Expression::Output => {
                     opcodes.push(Opcode::Print);
                 }
-                Expression::Input => todo!(),
+                Expression::Input => {
+                    opcodes.push(Opcode::Read);
+                }
                 Expression::Clear => {
                     opcodes.push(Opcode::Clear);
                 }
@@ -48,7 +145,7 @@ impl Interpreter {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Opcode {
     DecVal(u8),
     IncVal(u8),
@@ -60,6 +157,42 @@ pub enum Opcode {
     StartLoop(usize),
     EndLoop(usize),
     Print,
+    Read,
+    Scan(isize),
+    StartLoopMovePtr(usize, isize),
+    MovePtrEndLoop(isize, usize),
+    ClearMovePtr(isize),
+    MovePtrMulVal(isize, isize, u8),
+}
+
+/// Unfused opcodes print as their `Debug` form, fused ones as the opcodes they replace.
+impl std::fmt::Display for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let pointer = |offset: isize| match offset {
+            ..0 => Opcode::DecPtr(offset.unsigned_abs()),
+            _ => Opcode::IncPtr(offset as usize),
+        };
+
+        match *self {
+            Opcode::Scan(stride) => write!(f, "[{:?}]", pointer(stride)),
+            Opcode::StartLoopMovePtr(end, offset) => {
+                write!(f, "{:?} + {:?}", Opcode::StartLoop(end), pointer(offset))
+            }
+            Opcode::MovePtrEndLoop(offset, start) => {
+                write!(f, "{:?} + {:?}", pointer(offset), Opcode::EndLoop(start))
+            }
+            Opcode::ClearMovePtr(offset) => write!(f, "Clear + {:?}", pointer(offset)),
+            Opcode::MovePtrMulVal(offset, mul_offset, val) => {
+                write!(
+                    f,
+                    "{:?} + {:?}",
+                    pointer(offset),
+                    Opcode::MulVal(mul_offset, val)
+                )
+            }
+            _ => write!(f, "{self:?}"),
+        }
+    }
 }
 
 pub struct Opcodes(pub Vec<Opcode>);
@@ -70,7 +203,7 @@ impl Opcodes {
         opcode: &Opcode,
         f: &mut std::fmt::Formatter,
     ) -> std::fmt::Result {
-        if let Opcode::EndLoop(_) = opcode {
+        if let Opcode::EndLoop(_) | Opcode::MovePtrEndLoop(..) = opcode {
             *indent -= 1;
         }
 
@@ -78,11 +211,11 @@ impl Opcodes {
             write!(f, "\t")?;
         }
 
-        if let Opcode::StartLoop(_) = opcode {
+        if let Opcode::StartLoop(_) | Opcode::StartLoopMovePtr(..) = opcode {
             *indent += 1;
         }
 
-        write!(f, " {}", &format!("{opcode:?}\n"))
+        write!(f, " {}", &format!("{opcode}\n"))
     }
 }
 
@@ -100,85 +233,498 @@ impl std::fmt::Display for Opcodes {
     }
 }
 
-#[derive(Debug)]
+/// Cells on the tape of the [`Vm`], which snapshots have to match.
+pub const MEMORY_LENGTH: usize = 30_000;
+
+/// The cell the pointer of every engine starts at, leaving room to move left first.
+pub const START_POINTER: usize = 100;
+
+/// The error of an engine whose pointer moves off either end of the tape.
+pub fn outside_tape() -> io::Error {
+    io::Error::other("pointer moved outside the tape")
+}
+
+/// `pointer` moved by `offset`, or [`outside_tape`] when that leaves the tape.
+pub(crate) fn moved(pointer: usize, offset: isize) -> io::Result<usize> {
+    pointer
+        .checked_add_signed(offset)
+        .filter(|pointer| *pointer < MEMORY_LENGTH)
+        .ok_or_else(outside_tape)
+}
+
+/// The result of an engine's first run, which its later runs return instead of executing.
+#[derive(Debug, Default)]
+pub(crate) struct Outcome(Option<Result<(), (io::ErrorKind, String)>>);
+
+impl Outcome {
+    /// The result of the first run, once there was one.
+    pub(crate) fn repeat(&self) -> Option<io::Result<()>> {
+        match self.0.as_ref()? {
+            Ok(()) => Some(Ok(())),
+            Err((kind, message)) => Some(Err(io::Error::new(*kind, message.as_str()))),
+        }
+    }
+
+    pub(crate) fn record(&mut self, result: io::Result<()>) -> io::Result<()> {
+        self.0 = Some(match &result {
+            Ok(()) => Ok(()),
+            Err(error) => Err((error.kind(), error.to_string())),
+        });
+        result
+    }
+}
+
 pub struct Vm {
-    pub opcodes: Vec<Opcode>,
+    // a sixth of the size of the opcodes, which are decoded as they run
+    code: Bytecode,
     index: usize,
     pointer: usize,
-    memory: [u8; 30_000],
+    memory: [u8; MEMORY_LENGTH],
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+    input_position: u64,
+    // bytes read by steps that were stepped back, read again before `input`, last first
+    unread: Vec<u8>,
+    output_count: u64,
+    journal: Option<Journal>,
+    profile: Option<Profile>,
+    error: Option<io::Error>,
+    outcome: Outcome,
+}
+
+impl std::fmt::Debug for Vm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Vm")
+            .field("code", &self.code)
+            .field("index", &self.index)
+            .field("pointer", &self.pointer)
+            .field("input_position", &self.input_position)
+            .field("output_count", &self.output_count)
+            .field("journal", &self.journal)
+            .field("profile", &self.profile)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Vm {
     pub fn from(opcodes: &[Opcode]) -> Self {
         Self {
-            pointer: 100,
+            pointer: START_POINTER,
             index: 0,
-            opcodes: opcodes.to_vec(),
-            memory: [0; 30_000],
+            code: Bytecode::encode(opcodes),
+            memory: [0; MEMORY_LENGTH],
+            input: Box::new(io::stdin()),
+            output: Box::new(io::stdout()),
+            input_position: 0,
+            unread: vec![],
+            output_count: 0,
+            journal: None,
+            profile: None,
+            error: None,
+            outcome: Outcome::default(),
+        }
+    }
+
+    /// Reads the bytes for `,` from `input` instead of stdin; end of input reads as 0.
+    pub fn set_input<R: Read + 'static>(&mut self, input: R) {
+        self.input = Box::new(input);
+    }
+
+    pub fn set_output<W: Write + 'static>(&mut self, output: W) {
+        self.output = Box::new(output);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            fingerprint: Snapshot::fingerprint(&self.code.decode()),
+            index: self.index,
+            pointer: self.pointer,
+            input_position: self.input_position,
+            output_count: self.output_count,
+            memory: self.memory.to_vec(),
         }
     }
-    pub fn run(&mut self) {
+
+    /// Continues from `snapshot`, skipping the input it had already consumed.
+    ///
+    /// The input has to be set beforehand and is expected to start where the original
+    /// run's did; output produced before the snapshot is not written again.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        if snapshot.fingerprint != Snapshot::fingerprint(&self.code.decode()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "snapshot was taken from a different program",
+            ));
+        }
+        if snapshot.memory.len() != self.memory.len() || snapshot.pointer >= self.memory.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "snapshot tape does not fit the vm",
+            ));
+        }
+        if snapshot.index > self.code.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "snapshot index is outside the program",
+            ));
+        }
+
+        let skipped = io::copy(
+            &mut self.input.by_ref().take(snapshot.input_position),
+            &mut io::sink(),
+        )?;
+        if skipped != snapshot.input_position {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "input ends before the snapshot's input position",
+            ));
+        }
+
+        self.index = snapshot.index;
+        self.pointer = snapshot.pointer;
+        self.memory.copy_from_slice(&snapshot.memory);
+        self.input_position = snapshot.input_position;
+        self.unread.clear();
+        self.output_count = snapshot.output_count;
+        Ok(())
+    }
+
+    /// Keeps undo information for the last `capacity` steps so they can be stepped back.
+    pub fn enable_journal(&mut self, capacity: usize) {
+        self.journal = Some(Journal::new(capacity));
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn code(&self) -> &Bytecode {
+        &self.code
+    }
+
+    pub fn opcode(&self) -> Option<Opcode> {
+        self.code.get(self.index)
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.index >= self.code.len()
+    }
+
+    /// Runs to the end of the program, or to the first error, which [`Vm::take_error`]
+    /// would otherwise hold.
+    pub fn run(&mut self) -> io::Result<()> {
         while self.step().is_some() {}
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.output.flush(),
+        }
+    }
+
+    /// Why the last [`Vm::step`] returned `None` before the end of the program.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Counts executions per opcode and accesses per cell from now on.
+    pub fn enable_profiler(&mut self) {
+        self.profile = Some(Profile::new(self.code.len(), self.memory.len()));
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn step_back(&mut self) -> Option<()> {
+        let entry = self.journal.as_mut()?.undo()?;
+
+        self.index = entry.index;
+        self.pointer = entry.pointer;
+        if entry.input {
+            self.unread.push(self.memory[entry.pointer]);
+            self.input_position -= 1;
+        }
+        if let Some((cell, value)) = entry.cell {
+            self.memory[cell] = value;
+        }
+        Some(())
+    }
+
+    /// Steps back until the opcode at `index` is next, or returns `None` once the journal runs out.
+    pub fn run_back_to(&mut self, index: usize) -> Option<()> {
+        self.step_back()?;
+        while self.index != index {
+            self.step_back()?;
+        }
+        Some(())
+    }
+
+    pub fn last_write(&self, cell: usize) -> Option<CellWrite> {
+        self.journal.as_ref()?.last_write(cell, &self.memory)
+    }
+
+    fn undo_entry(&self) -> Option<Entry> {
+        let cell = match self.code.get(self.index)? {
+            Opcode::DecVal(_)
+            | Opcode::IncVal(_)
+            | Opcode::Clear
+            | Opcode::Read
+            | Opcode::ClearMovePtr(_) => Some(self.pointer),
+            Opcode::MulVal(offset, _) => self.pointer.checked_add_signed(offset),
+            Opcode::MovePtrMulVal(offset, mul_offset, _) => self
+                .pointer
+                .checked_add_signed(offset)
+                .and_then(|pointer| pointer.checked_add_signed(mul_offset)),
+            Opcode::Copy(offset) => Some(self.pointer + offset),
+            _ => None,
+        };
+
+        Some(Entry {
+            index: self.index,
+            pointer: self.pointer,
+            cell: cell.and_then(|cell| Some((cell, *self.memory.get(cell)?))),
+            input: false,
+        })
     }
 
     pub fn step(&mut self) -> Option<()> {
-        match self.opcodes.get(self.index) {
-            None => return None,
-            Some(opcode) => match opcode {
-                Opcode::DecVal(amount) => {
-                    self.memory[self.pointer] = self.memory[self.pointer].wrapping_sub(*amount);
-                    self.index += 1;
-                }
-                Opcode::IncVal(amount) => {
-                    self.memory[self.pointer] = self.memory[self.pointer].wrapping_add(*amount);
-                    self.index += 1;
-                }
-                Opcode::DecPtr(amount) => {
-                    self.pointer -= amount;
-                    self.index += 1;
-                }
-                Opcode::IncPtr(amount) => {
-                    self.pointer += amount;
-                    self.index += 1;
-                }
-                Opcode::MulVal(offset, val) => {
-                    let offset = self.pointer.checked_add_signed(*offset)?;
+        let entry = match self.journal {
+            Some(_) => self.undo_entry(),
+            None => None,
+        };
 
-                    self.memory[offset] = self.memory[offset]
-                        .wrapping_add(self.memory[self.pointer].wrapping_mul(*val));
-                    self.index += 1;
-                }
-                Opcode::Copy(offset) => {
-                    self.memory[self.pointer + offset] =
-                        self.memory[self.pointer + offset].wrapping_add(self.memory[self.pointer]);
+        let opcode = self.code.get(self.index)?;
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(self.index, &opcode, self.pointer, self.memory[self.pointer]);
+        }
 
-                    self.index += 1;
-                }
-                Opcode::Clear => {
-                    self.memory[self.pointer] = 0;
+        let input_position = self.input_position;
+        if let Err(error) = self.execute(opcode) {
+            self.error = Some(error);
+            return None;
+        }
 
-                    self.index += 1;
-                }
-                &Opcode::StartLoop(index) => {
-                    let index = match self.memory[self.pointer] {
-                        0 => index,
-                        _ => self.index + 1,
-                    };
-                    self.index = index;
-                }
-                &Opcode::EndLoop(index) => {
-                    let index = match self.memory[self.pointer] {
-                        0 => self.index + 1,
-                        _ => index,
-                    };
-                    self.index = index;
+        if let (Some(journal), Some(entry)) = (self.journal.as_mut(), entry) {
+            journal.record(Entry {
+                input: self.input_position != input_position,
+                ..entry
+            });
+        }
+        Some(())
+    }
+
+    /// The cell `offset` away from the pointer, or an error when it is off the tape.
+    fn cell(&self, offset: isize) -> io::Result<usize> {
+        moved(self.pointer, offset)
+    }
+
+    /// The next input byte, taking the ones given back by [`Vm::step_back`] first.
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.unread.pop() {
+            return Ok(Some(byte));
+        }
+
+        let mut byte = [0];
+        Ok(match self.input.read(&mut byte)? {
+            0 => None,
+            _ => Some(byte[0]),
+        })
+    }
+
+    /// Leaves the index at `opcode` when it fails, so the vm stops where the error happened.
+    fn execute(&mut self, opcode: Opcode) -> io::Result<()> {
+        match opcode {
+            Opcode::DecVal(amount) => {
+                self.memory[self.pointer] = self.memory[self.pointer].wrapping_sub(amount);
+                self.index += 1;
+            }
+            Opcode::IncVal(amount) => {
+                self.memory[self.pointer] = self.memory[self.pointer].wrapping_add(amount);
+                self.index += 1;
+            }
+            Opcode::DecPtr(amount) => {
+                self.pointer = self.pointer.checked_sub(amount).ok_or_else(outside_tape)?;
+                self.index += 1;
+            }
+            Opcode::IncPtr(amount) => {
+                self.pointer = self.cell(isize::try_from(amount).map_err(|_| outside_tape())?)?;
+                self.index += 1;
+            }
+            // skipped for an empty cell, whose target may lie off the tape
+            Opcode::MulVal(_, _) | Opcode::Copy(_) if self.memory[self.pointer] == 0 => {
+                self.index += 1;
+            }
+            Opcode::MulVal(offset, val) => {
+                let offset = self.cell(offset)?;
+
+                self.memory[offset] =
+                    self.memory[offset].wrapping_add(self.memory[self.pointer].wrapping_mul(val));
+                self.index += 1;
+            }
+            Opcode::Copy(offset) => {
+                let offset = self.cell(offset as isize)?;
+
+                self.memory[offset] = self.memory[offset].wrapping_add(self.memory[self.pointer]);
+                self.index += 1;
+            }
+            Opcode::Clear => {
+                self.memory[self.pointer] = 0;
+
+                self.index += 1;
+            }
+            Opcode::StartLoop(index) => {
+                let index = match self.memory[self.pointer] {
+                    0 => index,
+                    _ => self.index + 1,
+                };
+                self.index = index;
+            }
+            Opcode::EndLoop(index) => {
+                let index = match self.memory[self.pointer] {
+                    0 => self.index + 1,
+                    _ => index,
+                };
+                self.index = index;
+            }
+            Opcode::Print => {
+                self.output.write_all(&[self.memory[self.pointer]])?;
+                self.output_count += 1;
+                self.index += 1;
+            }
+            Opcode::Read => {
+                self.memory[self.pointer] = match self.read()? {
+                    Some(byte) => {
+                        self.input_position += 1;
+                        byte
+                    }
+                    None => 0,
+                };
+                self.index += 1;
+            }
+            Opcode::Scan(stride) => {
+                while self.memory[self.pointer] != 0 {
+                    self.pointer = self.cell(stride)?;
                 }
-                Opcode::Print => {
-                    print!("{}", self.memory[self.pointer] as char); //ToDo reactor this
-                    self.index += 1;
+                self.index += 1;
+            }
+            Opcode::StartLoopMovePtr(index, offset) => {
+                let index = match self.memory[self.pointer] {
+                    0 => index,
+                    _ => {
+                        self.pointer = self.cell(offset)?;
+                        self.index + 1
+                    }
+                };
+                self.index = index;
+            }
+            Opcode::MovePtrEndLoop(offset, index) => {
+                self.pointer = self.cell(offset)?;
+
+                let index = match self.memory[self.pointer] {
+                    0 => self.index + 1,
+                    _ => index,
+                };
+                self.index = index;
+            }
+            Opcode::ClearMovePtr(offset) => {
+                let pointer = self.cell(offset)?;
+                self.memory[self.pointer] = 0;
+                self.pointer = pointer;
+                self.index += 1;
+            }
+            Opcode::MovePtrMulVal(offset, mul_offset, val) => {
+                let pointer = self.cell(offset)?;
+
+                if self.memory[pointer] != 0 {
+                    let offset = moved(pointer, mul_offset)?;
+                    self.memory[offset] =
+                        self.memory[offset].wrapping_add(self.memory[pointer].wrapping_mul(val));
                 }
-            },
+                self.pointer = pointer;
+                self.index += 1;
+            }
         };
-        Some(())
+        Ok(())
+    }
+}
+
+impl Engine for Vm {
+    fn set_input(&mut self, input: Box<dyn Read>) {
+        self.input = input;
+    }
+
+    fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        if let Some(result) = self.outcome.repeat() {
+            return result;
+        }
+        let result = Vm::run(self);
+        self.outcome.record(result)
+    }
+
+    fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+}
+
+/// Helpers for the tests that compare engines and backends against the [`Vm`].
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{
+        cell::RefCell,
+        io::{self, Cursor, Write},
+        rc::Rc,
+    };
+
+    use crate::backends::vm::Engine;
+
+    /// Output that stays readable after it is handed to an engine.
+    #[derive(Clone, Default)]
+    pub struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Buffer {
+        pub fn bytes(&self) -> Vec<u8> {
+            self.0.borrow().clone()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs `engine` on `input` to the end, giving its output, pointer and tape.
+    pub fn run(mut engine: Box<dyn Engine>, input: &[u8]) -> (Vec<u8>, usize, Vec<u8>) {
+        let output = Buffer::default();
+        engine.set_input(Box::new(Cursor::new(input.to_vec())));
+        engine.set_output(Box::new(output.clone()));
+        engine.run().unwrap();
+
+        (output.bytes(), engine.pointer(), engine.memory().to_vec())
     }
 }
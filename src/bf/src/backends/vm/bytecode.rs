@@ -0,0 +1,386 @@
+use std::io::{self, Read, Write};
+
+use crate::backends::vm::{MEMORY_LENGTH, Opcode};
+
+const MAGIC: &[u8; 4] = b"BFBC";
+const VERSION: u32 = 1;
+
+/// Tag of a word whose payload indexes the constant pool instead of holding operands.
+const CONSTANT: u8 = 0xff;
+
+/// Bit widths of the operands packed into the 24-bit payload per tag, negative when signed.
+const LAYOUT: [&[i8]; 16] = [
+    &[8],         // DecVal
+    &[8],         // IncVal
+    &[24],        // DecPtr
+    &[24],        // IncPtr
+    &[-16, 8],    // MulVal
+    &[24],        // Copy
+    &[],          // Clear
+    &[24],        // StartLoop
+    &[24],        // EndLoop
+    &[],          // Print
+    &[],          // Read
+    &[-24],       // Scan
+    &[16, -8],    // StartLoopMovePtr
+    &[-8, 16],    // MovePtrEndLoop
+    &[-24],       // ClearMovePtr
+    &[-8, -8, 8], // MovePtrMulVal
+];
+
+/// Opcodes packed into one `u32` each: the tag in the low byte and the operands above it.
+///
+/// An opcode whose operands do not fit, like a jump past index 2^24, is kept whole in the
+/// constant pool. On disk a `.bfc` is the magic `BFBC`, a little-endian `u32` version, the
+/// `u32` word and constant counts, the words, every constant as a tag byte and three
+/// `i64` operands, and finally an FNV-1a `u64` checksum of everything before it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Bytecode {
+    code: Vec<u32>,
+    constants: Vec<Opcode>,
+}
+
+impl Bytecode {
+    pub fn encode(opcodes: &[Opcode]) -> Self {
+        let mut bytecode = Self::default();
+
+        for opcode in opcodes {
+            let (tag, operands) = Self::operands(*opcode);
+            let word = match Self::pack(tag, operands) {
+                Some(payload) => payload << 8 | tag as u32,
+                None => {
+                    bytecode.constants.push(*opcode);
+                    ((bytecode.constants.len() - 1) as u32) << 8 | CONSTANT as u32
+                }
+            };
+            bytecode.code.push(word);
+        }
+
+        bytecode
+    }
+
+    pub fn decode(&self) -> Vec<Opcode> {
+        (0..self.code.len())
+            .filter_map(|index| self.get(index))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// Unpacks the fields laid out by [`LAYOUT`], the first operand in the highest bits.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<Opcode> {
+        let word = *self.code.get(index)?;
+        let unsigned = |shift: u32, bits: u32| (word >> (shift + 8)) & ((1 << bits) - 1);
+        let signed =
+            |shift: u32, bits: u32| ((word << (24 - shift - bits)) as i32 >> (32 - bits)) as isize;
+
+        Some(match word as u8 {
+            0 => Opcode::DecVal(unsigned(0, 8) as u8),
+            1 => Opcode::IncVal(unsigned(0, 8) as u8),
+            2 => Opcode::DecPtr(unsigned(0, 24) as usize),
+            3 => Opcode::IncPtr(unsigned(0, 24) as usize),
+            4 => Opcode::MulVal(signed(8, 16), unsigned(0, 8) as u8),
+            5 => Opcode::Copy(unsigned(0, 24) as usize),
+            6 => Opcode::Clear,
+            7 => Opcode::StartLoop(unsigned(0, 24) as usize),
+            8 => Opcode::EndLoop(unsigned(0, 24) as usize),
+            9 => Opcode::Print,
+            10 => Opcode::Read,
+            11 => Opcode::Scan(signed(0, 24)),
+            12 => Opcode::StartLoopMovePtr(unsigned(8, 16) as usize, signed(0, 8)),
+            13 => Opcode::MovePtrEndLoop(signed(16, 8), unsigned(0, 16) as usize),
+            14 => Opcode::ClearMovePtr(signed(0, 24)),
+            15 => Opcode::MovePtrMulVal(signed(16, 8), signed(8, 8), unsigned(0, 8) as u8),
+            CONSTANT => return self.constants.get((word >> 8) as usize).copied(),
+            _ => return None,
+        })
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((self.code.len() as u32).to_le_bytes());
+        bytes.extend((self.constants.len() as u32).to_le_bytes());
+
+        for word in &self.code {
+            bytes.extend(word.to_le_bytes());
+        }
+        for constant in &self.constants {
+            let (tag, operands) = Self::operands(*constant);
+            bytes.push(tag);
+            for operand in operands {
+                bytes.extend(operand.to_le_bytes());
+            }
+        }
+
+        let checksum = checksum(&bytes);
+        output.write_all(&bytes)?;
+        output.write_all(&checksum.to_le_bytes())
+    }
+
+    pub fn read<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut bytes = vec![];
+        input.read_to_end(&mut bytes)?;
+
+        if bytes.len() < 24 || &bytes[..4] != MAGIC {
+            return Err(invalid_data("not a bytecode file"));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        if u32_at(4) != VERSION {
+            return Err(invalid_data("unsupported bytecode version"));
+        }
+
+        let (bytes, stored) = bytes.split_at(bytes.len() - 8);
+        if checksum(bytes).to_le_bytes() != stored {
+            return Err(invalid_data("bytecode checksum does not match"));
+        }
+        let (words, constants) = (u32_at(8) as usize, u32_at(12) as usize);
+        if bytes.len() != 16 + words * 4 + constants * 25 {
+            return Err(invalid_data("bytecode length does not match its header"));
+        }
+
+        let code = (0..words).map(|word| u32_at(16 + word * 4)).collect();
+        let constants = bytes[16 + words * 4..]
+            .chunks(25)
+            .map(|chunk| {
+                let mut operands = [0; 3];
+                for (operand, bytes) in operands.iter_mut().zip(chunk[1..].chunks(8)) {
+                    *operand = i64::from_le_bytes(bytes.try_into().unwrap());
+                }
+                Self::opcode(chunk[0], operands)
+                    .filter(|opcode| Self::operands(*opcode) == (chunk[0], operands))
+                    .ok_or_else(|| invalid_data("invalid bytecode constant"))
+            })
+            .collect::<io::Result<_>>()?;
+
+        let bytecode = Self { code, constants };
+        bytecode.validate()?;
+        Ok(bytecode)
+    }
+
+    /// Rejects unknown tags, moves as long as the tape and loops whose jumps miss their
+    /// partner, so the engines can trust their operands. An opener targets its closer, or
+    /// the opcode after it once fused by [`Peephole`](super::peephole::Peephole), and a
+    /// closer targets its opener.
+    fn validate(&self) -> io::Result<()> {
+        let mut openers = vec![];
+
+        for index in 0..self.code.len() {
+            let opcode = self.get(index);
+            if opcode.is_some_and(|opcode| {
+                Self::offsets(opcode)
+                    .iter()
+                    .any(|offset| *offset >= MEMORY_LENGTH)
+            }) {
+                return Err(invalid_data("bytecode moves past the end of the tape"));
+            }
+
+            match opcode {
+                None => return Err(invalid_data("invalid bytecode instruction")),
+                Some(Opcode::StartLoop(_) | Opcode::StartLoopMovePtr(..)) => openers.push(index),
+                Some(Opcode::EndLoop(start) | Opcode::MovePtrEndLoop(_, start)) => {
+                    let opener = openers
+                        .pop()
+                        .ok_or_else(|| invalid_data("unbalanced bytecode loops"))?;
+                    let end = match self.get(opener) {
+                        Some(Opcode::StartLoop(end) | Opcode::StartLoopMovePtr(end, _)) => end,
+                        _ => unreachable!("only openers are pushed"),
+                    };
+                    if start != opener || (end != index && end != index + 1) {
+                        return Err(invalid_data("bytecode loop jumps past its partner"));
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+
+        match openers.is_empty() {
+            true => Ok(()),
+            false => Err(invalid_data("unbalanced bytecode loops")),
+        }
+    }
+
+    /// The distances of the pointer moves and cells `opcode` reaches, 0 where it has none.
+    fn offsets(opcode: Opcode) -> [usize; 2] {
+        match opcode {
+            Opcode::DecPtr(amount) | Opcode::IncPtr(amount) | Opcode::Copy(amount) => [amount, 0],
+            Opcode::MulVal(offset, _)
+            | Opcode::Scan(offset)
+            | Opcode::StartLoopMovePtr(_, offset)
+            | Opcode::MovePtrEndLoop(offset, _)
+            | Opcode::ClearMovePtr(offset) => [offset.unsigned_abs(), 0],
+            Opcode::MovePtrMulVal(offset, mul_offset, _) => {
+                [offset.unsigned_abs(), mul_offset.unsigned_abs()]
+            }
+            _ => [0; 2],
+        }
+    }
+
+    fn pack(tag: u8, operands: [i64; 3]) -> Option<u32> {
+        let mut payload: u32 = 0;
+
+        for (&operand, &width) in operands.iter().zip(LAYOUT[tag as usize]) {
+            let bits = width.unsigned_abs() as u32;
+            let range = match width < 0 {
+                true => -(1 << (bits - 1))..1 << (bits - 1),
+                false => 0..1 << bits,
+            };
+            if !range.contains(&operand) {
+                return None;
+            }
+            payload = payload << bits | (operand as u32 & ((1 << bits) - 1));
+        }
+        Some(payload)
+    }
+
+    /// The tag and operands of `opcode`, padded with zeros, as a constant stores them.
+    pub(crate) fn operands(opcode: Opcode) -> (u8, [i64; 3]) {
+        match opcode {
+            Opcode::DecVal(amount) => (0, [amount as i64, 0, 0]),
+            Opcode::IncVal(amount) => (1, [amount as i64, 0, 0]),
+            Opcode::DecPtr(amount) => (2, [amount as i64, 0, 0]),
+            Opcode::IncPtr(amount) => (3, [amount as i64, 0, 0]),
+            Opcode::MulVal(offset, val) => (4, [offset as i64, val as i64, 0]),
+            Opcode::Copy(offset) => (5, [offset as i64, 0, 0]),
+            Opcode::Clear => (6, [0; 3]),
+            Opcode::StartLoop(index) => (7, [index as i64, 0, 0]),
+            Opcode::EndLoop(index) => (8, [index as i64, 0, 0]),
+            Opcode::Print => (9, [0; 3]),
+            Opcode::Read => (10, [0; 3]),
+            Opcode::Scan(stride) => (11, [stride as i64, 0, 0]),
+            Opcode::StartLoopMovePtr(index, offset) => (12, [index as i64, offset as i64, 0]),
+            Opcode::MovePtrEndLoop(offset, index) => (13, [offset as i64, index as i64, 0]),
+            Opcode::ClearMovePtr(offset) => (14, [offset as i64, 0, 0]),
+            Opcode::MovePtrMulVal(offset, mul_offset, val) => {
+                (15, [offset as i64, mul_offset as i64, val as i64])
+            }
+        }
+    }
+
+    fn opcode(tag: u8, [first, second, third]: [i64; 3]) -> Option<Opcode> {
+        Some(match tag {
+            0 => Opcode::DecVal(first as u8),
+            1 => Opcode::IncVal(first as u8),
+            2 => Opcode::DecPtr(first as usize),
+            3 => Opcode::IncPtr(first as usize),
+            4 => Opcode::MulVal(first as isize, second as u8),
+            5 => Opcode::Copy(first as usize),
+            6 => Opcode::Clear,
+            7 => Opcode::StartLoop(first as usize),
+            8 => Opcode::EndLoop(first as usize),
+            9 => Opcode::Print,
+            10 => Opcode::Read,
+            11 => Opcode::Scan(first as isize),
+            12 => Opcode::StartLoopMovePtr(first as usize, second as isize),
+            13 => Opcode::MovePtrEndLoop(first as isize, second as usize),
+            14 => Opcode::ClearMovePtr(first as isize),
+            15 => Opcode::MovePtrMulVal(first as isize, second as isize, third as u8),
+            _ => return None,
+        })
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::backends::vm::{
+        Interpreter, MEMORY_LENGTH, Opcode, bytecode::Bytecode, peephole::Peephole,
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    /// A loop long enough for its opener to jump to the largest index that fits a word.
+    fn longest_loop() -> Vec<Opcode> {
+        let mut opcodes = vec![Opcode::StartLoopMovePtr(65_535, -128)];
+        opcodes.extend(vec![Opcode::Scan(1 - MEMORY_LENGTH as isize); 65_533]);
+        opcodes.push(Opcode::MovePtrEndLoop(127, 0));
+        opcodes
+    }
+
+    #[test_case(vec![Opcode::IncVal(255), Opcode::DecPtr(3), Opcode::MulVal(-7, 4)]; "small operands")]
+    #[test_case(vec![Opcode::MovePtrMulVal(-128, 127, 9), Opcode::ClearMovePtr(-1)]; "signed fields")]
+    #[test_case(longest_loop(); "field limits")]
+    #[test_case(
+        vec![
+            Opcode::MovePtrMulVal(300, -300, 2),
+            Opcode::StartLoopMovePtr(2, 1_000),
+            Opcode::MovePtrEndLoop(-1_000, 1),
+        ];
+        "constant pool"
+    )]
+    fn round_trip(opcodes: Vec<Opcode>) {
+        let bytecode = Bytecode::encode(&opcodes);
+        let mut bytes = vec![];
+        bytecode.write(&mut bytes).unwrap();
+
+        let read = Bytecode::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(read.decode(), opcodes);
+        assert_eq!(read, bytecode);
+    }
+
+    #[test]
+    fn one_word_per_opcode() {
+        let text = std::fs::read_to_string(format!(
+            "{}/../../data/programs/mandelbrot.bf",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let opcodes = Peephole::optimize(&Interpreter::interpret(&Pipeline::execute(&text)));
+        let bytecode = Bytecode::encode(&opcodes);
+
+        assert_eq!(bytecode.len(), opcodes.len());
+        assert!(bytecode.constants.is_empty());
+        assert_eq!(bytecode.decode(), opcodes);
+    }
+
+    #[test_case(vec![Opcode::StartLoop(1), Opcode::Print]; "unclosed")]
+    #[test_case(vec![Opcode::Print, Opcode::EndLoop(0)]; "unopened")]
+    #[test_case(vec![Opcode::StartLoop(3), Opcode::EndLoop(0)]; "opener past its closer")]
+    #[test_case(
+        vec![Opcode::StartLoop(3), Opcode::StartLoop(2), Opcode::EndLoop(0), Opcode::EndLoop(1)];
+        "closer at the outer opener"
+    )]
+    #[test_case(vec![Opcode::DecPtr(1 << 63)]; "move past isize")]
+    #[test_case(vec![Opcode::IncPtr(MEMORY_LENGTH)]; "move as long as the tape")]
+    #[test_case(vec![Opcode::MulVal(-30_000, 2)]; "offset as long as the tape")]
+    #[test_case(vec![Opcode::MovePtrMulVal(1, isize::MIN, 2)]; "offset past isize")]
+    fn reject_opcodes(opcodes: Vec<Opcode>) {
+        let mut bytes = vec![];
+        Bytecode::encode(&opcodes).write(&mut bytes).unwrap();
+
+        let error = Bytecode::read(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test_case(0, 0x01; "bad magic")]
+    #[test_case(4, 0x01; "bad version")]
+    #[test_case(16, 0x40; "bad checksum")]
+    fn reject_corrupt(at: usize, flip: u8) {
+        let mut bytes = vec![];
+        Bytecode::encode(&[Opcode::Clear, Opcode::Print])
+            .write(&mut bytes)
+            .unwrap();
+        bytes[at] ^= flip;
+
+        assert!(Bytecode::read(&mut Cursor::new(bytes)).is_err());
+    }
+}
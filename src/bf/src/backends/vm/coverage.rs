@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use crate::{
+    backends::vm::{Opcode, profiler::Profile},
+    core::source_map::{SourceMap, Span},
+};
+
+/// Execution counts of one program aggregated over any number of runs, mapped to its source.
+pub struct Coverage {
+    path: String,
+    spans: Vec<Span>,
+    loops: Vec<(usize, usize)>,
+    profile: Profile,
+}
+
+impl Coverage {
+    pub fn new(path: &str, opcodes: &[Opcode], source_map: &SourceMap) -> Self {
+        let loops = opcodes
+            .iter()
+            .enumerate()
+            .filter_map(|(start, opcode)| match *opcode {
+                Opcode::StartLoop(end) => Some((start, end)),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            path: path.to_string(),
+            spans: source_map.spans().to_vec(),
+            loops,
+            profile: Profile::new(opcodes.len(), 0),
+        }
+    }
+
+    /// Adds the counters of one run, made with the profiler enabled.
+    pub fn add(&mut self, profile: &Profile) {
+        self.profile.merge(profile);
+    }
+
+    /// Hit count per source line holding at least one command.
+    pub fn lines(&self) -> BTreeMap<usize, u64> {
+        let mut lines = BTreeMap::new();
+
+        for (span, count) in self.spans.iter().zip(self.profile.opcodes()) {
+            for line in span.start.line..=span.end.line {
+                let hits = lines.entry(line).or_insert(0);
+                *hits = (*hits).max(*count);
+            }
+        }
+        lines
+    }
+
+    /// Writes a single lcov record; every loop is a branch taken into its body or past it.
+    pub fn write_lcov<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        writeln!(output, "TN:")?;
+        writeln!(output, "SF:{}", self.path)?;
+
+        let mut branches_hit = 0;
+        for (block, &(start, end)) in self.loops.iter().enumerate() {
+            let line = self.spans.get(start).map_or(0, |span| span.start.line);
+            let (entries, iterations) = self.profile.loop_counts(start, end);
+
+            for (branch, taken) in [iterations, entries].into_iter().enumerate() {
+                match (self.profile.opcodes()[start], taken) {
+                    (0, _) => writeln!(output, "BRDA:{line},{block},{branch},-")?,
+                    (_, taken) => {
+                        branches_hit += usize::from(taken > 0);
+                        writeln!(output, "BRDA:{line},{block},{branch},{taken}")?
+                    }
+                }
+            }
+        }
+        writeln!(output, "BRF:{}", self.loops.len() * 2)?;
+        writeln!(output, "BRH:{branches_hit}")?;
+
+        let lines = self.lines();
+        for (line, hits) in &lines {
+            writeln!(output, "DA:{line},{hits}")?;
+        }
+        writeln!(output, "LF:{}", lines.len())?;
+        writeln!(
+            output,
+            "LH:{}",
+            lines.values().filter(|hits| **hits > 0).count()
+        )?;
+
+        writeln!(output, "end_of_record")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, sink};
+
+    use crate::backends::vm::{Interpreter, Vm, coverage::Coverage};
+    use crate::core::{pipeline::Pipeline, source_map::SourceMap};
+    use pretty_assertions::assert_eq;
+
+    const PROGRAM: &str = ",\n[\n>+\n<--\n]\n>.";
+
+    fn coverage(inputs: &[&[u8]]) -> Coverage {
+        let opcodes = Interpreter::interpret(&Pipeline::execute(PROGRAM));
+        let mut coverage = Coverage::new("test.bf", &opcodes, &SourceMap::new(PROGRAM));
+
+        for input in inputs {
+            let mut vm = Vm::from(&opcodes);
+            vm.set_input(Cursor::new(input.to_vec()));
+            vm.set_output(sink());
+            vm.enable_profiler();
+            vm.run().unwrap();
+
+            coverage.add(vm.profile().unwrap());
+        }
+        coverage
+    }
+
+    #[test]
+    fn lcov_single_run() {
+        let mut lcov = vec![];
+        coverage(&[b""]).write_lcov(&mut lcov).unwrap();
+
+        let expected = "TN:\nSF:test.bf\nBRDA:2,0,0,0\nBRDA:2,0,1,1\nBRF:2\nBRH:1\n\
+                        DA:1,1\nDA:2,1\nDA:3,0\nDA:4,0\nDA:5,1\nDA:6,1\nLF:6\nLH:4\nend_of_record\n";
+        assert_eq!(String::from_utf8(lcov).unwrap(), expected);
+    }
+
+    #[test]
+    fn aggregate_runs() {
+        let lines = coverage(&[b"", b"\x04", b"\x02"]).lines();
+
+        assert_eq!(
+            lines.into_iter().collect::<Vec<_>>(),
+            vec![(1, 3), (2, 4), (3, 3), (4, 3), (5, 4), (6, 3)]
+        );
+    }
+}
@@ -0,0 +1,572 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
+
+use crate::{
+    backends::vm::{Opcode, Vm},
+    core::source_map::{Position, SourceMap},
+};
+
+const HELP: &str = "\
+break <location>     set a breakpoint (b)
+delete <location>    remove a breakpoint (d)
+step [count]         execute one or more opcodes (s)
+next                 step over the loop starting at the current opcode (n)
+continue             run until a breakpoint, a watch or the end (c)
+until <location>     run to the given location (u)
+back [count]         step back one or more opcodes (r)
+back-to <location>   step back to the given location
+last-write <cell>    show the last journaled write to a cell
+watch <cell>         stop when a cell changes, e.g. 12, p, p+1, p-2 (w)
+unwatch <number>     remove a watch
+breakpoints          list breakpoints
+watches              list watches
+tape [radius]        show the cells around the pointer (t)
+where                show the current opcode and source (l)
+quit                 leave the debugger (q)
+
+a location is either an opcode index (42) or a source position (line:column)
+an empty line repeats the previous command";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Location {
+    Index(usize),
+    Position(Position),
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parse = |text: &str| {
+            text.parse::<usize>()
+                .map_err(|_| format!("invalid location `{text}`"))
+        };
+
+        match text.split_once(':') {
+            Some((line, column)) => Ok(Location::Position(Position {
+                line: parse(line)?,
+                column: parse(column)?,
+            })),
+            None => parse(text).map(Location::Index),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Watch {
+    Cell(usize),
+    Relative(isize),
+}
+
+impl Watch {
+    fn address(&self, vm: &Vm) -> Option<usize> {
+        match *self {
+            Watch::Cell(cell) => Some(cell),
+            Watch::Relative(offset) => vm.pointer().checked_add_signed(offset),
+        }
+    }
+
+    fn value(&self, vm: &Vm) -> Option<u8> {
+        self.address(vm)
+            .and_then(|address| vm.memory().get(address).copied())
+    }
+}
+
+impl FromStr for Watch {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid watch `{text}`");
+
+        match text.strip_prefix('p') {
+            Some("") => Ok(Watch::Relative(0)),
+            Some(offset) => {
+                let offset = offset.strip_prefix('+').unwrap_or(offset);
+                offset.parse().map(Watch::Relative).map_err(|_| error())
+            }
+            None => text.parse().map(Watch::Cell).map_err(|_| error()),
+        }
+    }
+}
+
+impl std::fmt::Display for Watch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Watch::Cell(cell) => write!(f, "{cell}"),
+            Watch::Relative(0) => write!(f, "p"),
+            Watch::Relative(offset) => write!(f, "p{offset:+}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Stop {
+    Step,
+    Breakpoint(usize),
+    Watch(Watch, Option<u8>, Option<u8>),
+    Halted,
+    /// The opcode at the index failed, like a move off the tape or a failed write.
+    Error(String),
+    HistoryExhausted,
+}
+
+/// Drives a [`Vm`] one `step` at a time and reports where it stops in the source.
+pub struct Debugger {
+    vm: Vm,
+    source_map: SourceMap,
+    lines: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    watches: Vec<Watch>,
+}
+
+impl Debugger {
+    pub fn new(vm: Vm, source_map: SourceMap, text: &str) -> Self {
+        Self {
+            vm,
+            source_map,
+            lines: text.lines().map(String::from).collect(),
+            breakpoints: BTreeSet::new(),
+            watches: vec![],
+        }
+    }
+
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    pub fn resolve(&self, location: Location) -> Option<usize> {
+        match location {
+            Location::Index(index) if index < self.vm.code().len() => Some(index),
+            Location::Index(_) => None,
+            Location::Position(position) => self.source_map.index_of(position),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, location: Location) -> Option<usize> {
+        let index = self.resolve(location)?;
+        self.breakpoints.insert(index);
+        Some(index)
+    }
+
+    pub fn remove_breakpoint(&mut self, location: Location) -> Option<usize> {
+        let index = self.resolve(location)?;
+        self.breakpoints.remove(&index).then_some(index)
+    }
+
+    pub fn add_watch(&mut self, watch: Watch) {
+        self.watches.push(watch);
+    }
+
+    pub fn remove_watch(&mut self, number: usize) -> Option<Watch> {
+        (number < self.watches.len()).then(|| self.watches.remove(number))
+    }
+
+    pub fn step(&mut self) -> Stop {
+        self.run_until(|_| true)
+    }
+
+    pub fn step_over(&mut self) -> Stop {
+        match self.vm.opcode() {
+            Some(Opcode::StartLoop(end_index)) => self.run_until(|vm| vm.index() == end_index + 1),
+            _ => self.step(),
+        }
+    }
+
+    pub fn resume(&mut self) -> Stop {
+        self.run_until(|_| false)
+    }
+
+    pub fn run_to(&mut self, index: usize) -> Stop {
+        self.run_until(|vm| vm.index() == index)
+    }
+
+    pub fn step_back(&mut self) -> Stop {
+        match self.vm.step_back() {
+            Some(()) => Stop::Step,
+            None => Stop::HistoryExhausted,
+        }
+    }
+
+    pub fn run_back_to(&mut self, index: usize) -> Stop {
+        match self.vm.run_back_to(index) {
+            Some(()) => Stop::Step,
+            None => Stop::HistoryExhausted,
+        }
+    }
+
+    fn run_until(&mut self, target: impl Fn(&Vm) -> bool) -> Stop {
+        loop {
+            let values = self.watch_values();
+
+            if self.vm.step().is_none() {
+                return match self.vm.take_error() {
+                    Some(error) => Stop::Error(error.to_string()),
+                    None => Stop::Halted,
+                };
+            }
+
+            let changed = self
+                .watches
+                .iter()
+                .zip(values)
+                .map(|(watch, old)| (*watch, old, watch.value(&self.vm)))
+                .find(|(_, old, new)| old != new);
+            if let Some((watch, old, new)) = changed {
+                return Stop::Watch(watch, old, new);
+            }
+
+            // after the watches, so a change made by the last opcode still stops
+            if self.vm.is_halted() {
+                return Stop::Halted;
+            }
+
+            if target(&self.vm) {
+                return Stop::Step;
+            }
+
+            if self.breakpoints.contains(&self.vm.index()) {
+                return Stop::Breakpoint(self.vm.index());
+            }
+        }
+    }
+
+    fn watch_values(&self) -> Vec<Option<u8>> {
+        self.watches
+            .iter()
+            .map(|watch| watch.value(&self.vm))
+            .collect()
+    }
+
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        let mut previous = String::new();
+
+        self.write_where(&mut output)?;
+        write!(output, "(bf) ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let line = match line?.trim() {
+                "" => previous.clone(),
+                line => line.to_string(),
+            };
+
+            if !self.execute(&line, &mut output)? {
+                break;
+            }
+            previous = line;
+
+            write!(output, "(bf) ")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn execute<W: Write>(&mut self, line: &str, output: &mut W) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next();
+
+        match (command, argument) {
+            ("" | "help" | "h", _) => writeln!(output, "{HELP}")?,
+            ("quit" | "q", _) => return Ok(false),
+            ("break" | "b", Some(location)) => match location.parse() {
+                Ok(location) => match self.add_breakpoint(location) {
+                    Some(index) => writeln!(output, "breakpoint at {}", self.describe(index))?,
+                    None => writeln!(output, "no opcode at {location:?}")?,
+                },
+                Err(error) => writeln!(output, "{error}")?,
+            },
+            ("delete" | "d", Some(location)) => match location.parse() {
+                Ok(location) => match self.remove_breakpoint(location) {
+                    Some(index) => writeln!(output, "deleted breakpoint at {index:0>5}")?,
+                    None => writeln!(output, "no breakpoint at {location:?}")?,
+                },
+                Err(error) => writeln!(output, "{error}")?,
+            },
+            ("step" | "s", count) => match count.map_or(Ok(1), str::parse::<usize>) {
+                Ok(count) => {
+                    let mut stop = Stop::Step;
+                    for _ in 0..count {
+                        stop = self.step();
+                        if stop != Stop::Step {
+                            break;
+                        }
+                    }
+                    self.write_stop(stop, output)?;
+                }
+                Err(_) => writeln!(output, "invalid count `{}`", count.unwrap_or_default())?,
+            },
+            ("next" | "n", _) => {
+                let stop = self.step_over();
+                self.write_stop(stop, output)?;
+            }
+            ("continue" | "c", _) => {
+                let stop = self.resume();
+                self.write_stop(stop, output)?;
+            }
+            ("until" | "u", Some(location)) => match location.parse() {
+                Ok(location) => match self.resolve(location) {
+                    Some(index) => {
+                        let stop = self.run_to(index);
+                        self.write_stop(stop, output)?;
+                    }
+                    None => writeln!(output, "no opcode at {location:?}")?,
+                },
+                Err(error) => writeln!(output, "{error}")?,
+            },
+            ("back" | "r", count) => match count.map_or(Ok(1), str::parse::<usize>) {
+                Ok(count) => {
+                    let mut stop = Stop::Step;
+                    for _ in 0..count {
+                        stop = self.step_back();
+                        if stop != Stop::Step {
+                            break;
+                        }
+                    }
+                    self.write_stop(stop, output)?;
+                }
+                Err(_) => writeln!(output, "invalid count `{}`", count.unwrap_or_default())?,
+            },
+            ("back-to", Some(location)) => match location.parse() {
+                Ok(location) => match self.resolve(location) {
+                    Some(index) => {
+                        let stop = self.run_back_to(index);
+                        self.write_stop(stop, output)?;
+                    }
+                    None => writeln!(output, "no opcode at {location:?}")?,
+                },
+                Err(error) => writeln!(output, "{error}")?,
+            },
+            ("last-write", Some(cell)) => {
+                match cell.parse().ok().map(|cell| self.vm.last_write(cell)) {
+                    Some(Some(write)) => writeln!(
+                        output,
+                        "step {}: {} wrote [{}] {} -> {}",
+                        write.step,
+                        self.describe(write.index),
+                        write.cell,
+                        write.old,
+                        write.new
+                    )?,
+                    Some(None) => writeln!(output, "no journaled write to {cell}")?,
+                    None => writeln!(output, "invalid cell `{cell}`")?,
+                }
+            }
+            ("watch" | "w", Some(watch)) => match watch.parse() {
+                Ok(watch) => {
+                    self.add_watch(watch);
+                    writeln!(output, "watch {}: {watch}", self.watches.len() - 1)?;
+                }
+                Err(error) => writeln!(output, "{error}")?,
+            },
+            ("unwatch", Some(number)) => {
+                match number.parse().ok().and_then(|n| self.remove_watch(n)) {
+                    Some(watch) => writeln!(output, "removed watch {watch}")?,
+                    None => writeln!(output, "no watch {number}")?,
+                }
+            }
+            ("breakpoints", _) => {
+                for index in &self.breakpoints {
+                    writeln!(output, "{}", self.describe(*index))?;
+                }
+            }
+            ("watches", _) => self.write_watches(output)?,
+            ("tape" | "t", radius) => match radius.map_or(Ok(8), str::parse::<usize>) {
+                Ok(radius) => self.write_tape(radius, output)?,
+                Err(_) => writeln!(output, "invalid radius `{}`", radius.unwrap_or_default())?,
+            },
+            ("where" | "l", _) => self.write_where(output)?,
+            (command, _) => writeln!(output, "unknown command `{command}`, try `help`")?,
+        }
+
+        Ok(true)
+    }
+
+    fn describe(&self, index: usize) -> String {
+        let opcode = self
+            .vm
+            .code()
+            .get(index)
+            .map(|opcode| format!("{opcode:?}"))
+            .unwrap_or_default();
+
+        match self.source_map.span(index) {
+            Some(span) => format!("{index:0>5} {opcode} at {span}"),
+            None => format!("{index:0>5} {opcode}"),
+        }
+    }
+
+    fn write_stop<W: Write>(&self, stop: Stop, output: &mut W) -> io::Result<()> {
+        match stop {
+            Stop::Step => {}
+            Stop::Breakpoint(index) => writeln!(output, "breakpoint {index:0>5}")?,
+            Stop::Watch(watch, old, new) => {
+                let value = |value: Option<u8>| value.map_or("-".to_string(), |v| v.to_string());
+                writeln!(output, "watch {watch}: {} -> {}", value(old), value(new))?
+            }
+            Stop::Halted => return writeln!(output, "program halted"),
+            Stop::Error(error) => writeln!(output, "error: {error}")?,
+            Stop::HistoryExhausted => writeln!(output, "no more history to step back")?,
+        }
+        self.write_where(output)
+    }
+
+    fn write_where<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let index = self.vm.index();
+        if self.vm.is_halted() {
+            return writeln!(output, "{index:0>5} end of program");
+        }
+        writeln!(output, "{}", self.describe(index))?;
+
+        if let Some(span) = self.source_map.span(index) {
+            let line = self
+                .lines
+                .get(span.start.line - 1)
+                .map_or("", String::as_str);
+            let end = match span.end.line == span.start.line {
+                true => span.end.column,
+                false => line.chars().count(),
+            };
+
+            writeln!(output, "{:>5} | {line}", span.start.line)?;
+            writeln!(
+                output,
+                "      | {}{}",
+                " ".repeat(span.start.column - 1),
+                "^".repeat(end + 1 - span.start.column)
+            )?;
+        }
+
+        self.write_watches(output)
+    }
+
+    fn write_watches<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for (number, watch) in self.watches.iter().enumerate() {
+            match (watch.address(&self.vm), watch.value(&self.vm)) {
+                (Some(address), Some(value)) => {
+                    writeln!(output, "watch {number}: {watch} = [{address}] {value}")?
+                }
+                _ => writeln!(output, "watch {number}: {watch} is off the tape")?,
+            }
+        }
+        Ok(())
+    }
+
+    fn write_tape<W: Write>(&self, radius: usize, output: &mut W) -> io::Result<()> {
+        let pointer = self.vm.pointer();
+        let memory = self.vm.memory();
+        let start = pointer.saturating_sub(radius);
+        let end = (pointer + radius + 1).min(memory.len());
+
+        for (address, value) in memory.iter().enumerate().take(end).skip(start) {
+            match address == pointer {
+                true => write!(output, "[{value}] ")?,
+                false => write!(output, "{value} ")?,
+            }
+        }
+        writeln!(output, "\n(cells {start}..{end}, pointer at {pointer})")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backends::vm::debugger::{Debugger, Location, Stop, Watch};
+    use crate::backends::vm::{Interpreter, Vm};
+    use crate::core::{
+        pipeline::Pipeline,
+        source_map::{Position, SourceMap},
+    };
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn debugger(text: &str) -> Debugger {
+        let opcodes = Interpreter::interpret(&Pipeline::execute(text));
+        Debugger::new(Vm::from(&opcodes), SourceMap::new(text), text)
+    }
+
+    #[test_case("12", Ok(Location::Index(12)))]
+    #[test_case("3:14", Ok(Location::Position(Position { line: 3, column: 14 })))]
+    #[test_case("3:", Err("invalid location ``".to_string()))]
+    fn parse_location(text: &str, expected: Result<Location, String>) {
+        assert_eq!(text.parse::<Location>(), expected);
+    }
+
+    #[test_case("p", Ok(Watch::Relative(0)))]
+    #[test_case("p+2", Ok(Watch::Relative(2)))]
+    #[test_case("p-1", Ok(Watch::Relative(-1)))]
+    #[test_case("7", Ok(Watch::Cell(7)))]
+    #[test_case("q", Err("invalid watch `q`".to_string()))]
+    fn parse_watch(text: &str, expected: Result<Watch, String>) {
+        assert_eq!(text.parse::<Watch>(), expected);
+    }
+
+    #[test]
+    fn breakpoint_by_position() {
+        let mut debugger = debugger("++\n>[>+<<]\n>>+");
+        let index = debugger.add_breakpoint(Location::Position(Position { line: 3, column: 1 }));
+
+        assert_eq!(index, Some(7));
+        assert_eq!(debugger.resume(), Stop::Breakpoint(7));
+        assert_eq!(debugger.vm().pointer(), 101);
+    }
+
+    #[test]
+    fn step_over_loop() {
+        let mut debugger = debugger("++++[>+++<--]>");
+        debugger.step();
+
+        assert_eq!(debugger.step_over(), Stop::Step);
+        assert_eq!(debugger.vm().memory()[101], 6);
+        assert_eq!(debugger.vm().index(), 7);
+    }
+
+    #[test]
+    fn watch_relative_cell() {
+        let mut debugger = debugger("+>++>+++");
+        debugger.add_watch(Watch::Relative(0));
+
+        assert_eq!(
+            debugger.resume(),
+            Stop::Watch(Watch::Relative(0), Some(0), Some(1))
+        );
+        assert_eq!(
+            debugger.resume(),
+            Stop::Watch(Watch::Relative(0), Some(1), Some(0))
+        );
+    }
+
+    #[test]
+    fn watch_on_last_opcode() {
+        let mut debugger = debugger(">+");
+        debugger.add_watch(Watch::Cell(101));
+
+        assert_eq!(
+            debugger.resume(),
+            Stop::Watch(Watch::Cell(101), Some(0), Some(1))
+        );
+        assert_eq!(debugger.resume(), Stop::Halted);
+    }
+
+    #[test]
+    fn error_off_the_tape() {
+        let mut debugger = debugger("+[<+]");
+
+        assert_eq!(
+            debugger.resume(),
+            Stop::Error("pointer moved outside the tape".to_string())
+        );
+        assert_eq!(debugger.vm().pointer(), 0);
+
+        let mut output = vec![];
+        debugger.repl(&b"continue\n"[..], &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("error: pointer moved outside the tape"),
+            "{output}"
+        );
+    }
+}
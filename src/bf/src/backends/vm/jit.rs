@@ -0,0 +1,419 @@
+use crate::{
+    backends::vm::{Engine, Interpreter, Vm},
+    core::ir::Expression,
+};
+
+/// Compiles `expressions` to machine code where the JIT is supported and falls back to
+/// the [`Vm`] everywhere else, or when the code cannot be mapped executable.
+pub fn compile(expressions: &[Expression]) -> Box<dyn Engine> {
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    if let Ok(jit) = x86_64::Jit::new(expressions) {
+        return Box::new(jit);
+    }
+
+    Box::new(Vm::from(&Interpreter::interpret(expressions)))
+}
+
+pub fn is_supported() -> bool {
+    cfg!(all(target_arch = "x86_64", target_os = "linux"))
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub mod x86_64 {
+    use std::io::{self, Read, Write};
+
+    use crate::{
+        backends::vm::{Engine, MEMORY_LENGTH, Outcome, START_POINTER, outside_tape},
+        core::ir::Expression,
+    };
+
+    const STATUS_OK: u64 = 0;
+    const STATUS_OUT_OF_BOUNDS: u64 = 1;
+    const STATUS_IO_ERROR: u64 = 2;
+
+    #[derive(Clone, Copy)]
+    enum Label {
+        Epilogue,
+        OutOfBounds,
+        IoError,
+    }
+
+    /// Emits x86-64 for one program.
+    ///
+    /// `rbx` holds the pointer as an index, `r12` the tape base and `r13` the [`Context`];
+    /// every pointer move is checked against the tape length with a single unsigned compare
+    /// before it reaches `rbx`, which so always stays on the tape.
+    struct Assembler {
+        code: Vec<u8>,
+        fixups: Vec<(usize, Label)>,
+    }
+
+    impl Assembler {
+        fn new() -> Self {
+            Self {
+                code: vec![],
+                fixups: vec![],
+            }
+        }
+
+        fn emit(&mut self, bytes: &[u8]) {
+            self.code.extend_from_slice(bytes);
+        }
+
+        fn emit_u32(&mut self, value: u32) {
+            self.emit(&value.to_le_bytes());
+        }
+
+        fn emit_jump(&mut self, opcode: &[u8], label: Label) {
+            self.emit(opcode);
+            self.fixups.push((self.code.len(), label));
+            self.emit_u32(0);
+        }
+
+        fn patch(&mut self, at: usize, target: usize) {
+            let relative = target as i64 - (at as i64 + 4);
+            self.code[at..at + 4].copy_from_slice(&(relative as i32).to_le_bytes());
+        }
+
+        fn immediate(value: usize) -> isize {
+            i32::try_from(value).expect("amount does not fit an immediate") as isize
+        }
+
+        /// Leaves `rbx` moved by `offset` in `rax`, or exits when that is off the tape.
+        fn check_offset(&mut self, offset: isize) {
+            // lea rax, [rbx + offset]; cmp rax, MEMORY_LENGTH; jae out_of_bounds
+            self.emit(&[0x48, 0x8d, 0x83]);
+            self.emit_u32(i32::try_from(offset).expect("offset does not fit") as u32);
+            self.emit(&[0x48, 0x3d]);
+            self.emit_u32(MEMORY_LENGTH as u32);
+            self.emit_jump(&[0x0f, 0x83], Label::OutOfBounds);
+        }
+
+        fn move_pointer(&mut self, offset: isize) {
+            self.check_offset(offset);
+            // mov rbx, rax
+            self.emit(&[0x48, 0x89, 0xc3]);
+        }
+
+        fn call(&mut self, function: usize) {
+            // mov rax, function; call rax
+            self.emit(&[0x48, 0xb8]);
+            self.emit(&(function as u64).to_le_bytes());
+            self.emit(&[0xff, 0xd0]);
+        }
+
+        fn assemble(&mut self, expressions: &[Expression]) {
+            for expression in expressions {
+                match *expression {
+                    // add byte [r12 + rbx], amount
+                    Expression::IncVal(amount) => self.emit(&[0x41, 0x80, 0x04, 0x1c, amount]),
+                    // sub byte [r12 + rbx], amount
+                    Expression::DecVal(amount) => self.emit(&[0x41, 0x80, 0x2c, 0x1c, amount]),
+                    Expression::IncPtr(amount) => self.move_pointer(Self::immediate(amount)),
+                    Expression::DecPtr(amount) => self.move_pointer(-Self::immediate(amount)),
+                    Expression::MulVal(offset, amount) => {
+                        // movzx ecx, byte [r12 + rbx]; test ecx, ecx; jz skip
+                        self.emit(&[0x41, 0x0f, 0xb6, 0x0c, 0x1c, 0x85, 0xc9, 0x0f, 0x84]);
+                        let skip = self.code.len();
+                        self.emit_u32(0);
+
+                        // skipped for an empty cell, whose target may lie off the tape
+                        self.check_offset(offset);
+                        // imul ecx, ecx, amount; add byte [r12 + rax], cl
+                        self.emit(&[0x69, 0xc9]);
+                        self.emit_u32(amount as u32);
+                        self.emit(&[0x41, 0x00, 0x0c, 0x04]);
+
+                        let end = self.code.len();
+                        self.patch(skip, end);
+                    }
+                    // mov byte [r12 + rbx], 0
+                    Expression::Clear => self.emit(&[0x41, 0xc6, 0x04, 0x1c, 0x00]),
+                    Expression::Loop(ref body) => {
+                        // cmp byte [r12 + rbx], 0; je end
+                        self.emit(&[0x41, 0x80, 0x3c, 0x1c, 0x00, 0x0f, 0x84]);
+                        let skip = self.code.len();
+                        self.emit_u32(0);
+
+                        let start = self.code.len();
+                        self.assemble(body);
+
+                        // cmp byte [r12 + rbx], 0; jne start
+                        self.emit(&[0x41, 0x80, 0x3c, 0x1c, 0x00, 0x0f, 0x85]);
+                        let repeat = self.code.len();
+                        self.emit_u32(0);
+
+                        self.patch(repeat, start);
+                        let end = self.code.len();
+                        self.patch(skip, end);
+                    }
+                    Expression::Output => {
+                        // mov rdi, r13; movzx esi, byte [r12 + rbx]
+                        self.emit(&[0x4c, 0x89, 0xef, 0x41, 0x0f, 0xb6, 0x34, 0x1c]);
+                        self.call(output as *const () as usize);
+                        // test rax, rax; jnz io_error
+                        self.emit(&[0x48, 0x85, 0xc0]);
+                        self.emit_jump(&[0x0f, 0x85], Label::IoError);
+                    }
+                    Expression::Input => {
+                        // mov rdi, r13
+                        self.emit(&[0x4c, 0x89, 0xef]);
+                        self.call(input as *const () as usize);
+                        // cmp rax, 0xff; ja io_error
+                        self.emit(&[0x48, 0x3d, 0xff, 0x00, 0x00, 0x00]);
+                        self.emit_jump(&[0x0f, 0x87], Label::IoError);
+                        // mov byte [r12 + rbx], al
+                        self.emit(&[0x41, 0x88, 0x04, 0x1c]);
+                    }
+                }
+            }
+        }
+
+        fn finish(mut self, expressions: &[Expression]) -> Vec<u8> {
+            // push rbp; push rbx; push r12; push r13; push r14
+            self.emit(&[0x55, 0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56]);
+            // mov r12, rdi; mov rbx, rsi; mov r13, rdx
+            self.emit(&[0x49, 0x89, 0xfc, 0x48, 0x89, 0xf3, 0x49, 0x89, 0xd5]);
+
+            self.assemble(expressions);
+
+            // xor eax, eax
+            self.emit(&[0x31, 0xc0]);
+            let epilogue = self.code.len();
+            // mov [r13], rbx; pop r14; pop r13; pop r12; pop rbx; pop rbp; ret
+            self.emit(&[0x49, 0x89, 0x5d, 0x00]);
+            self.emit(&[0x41, 0x5e, 0x41, 0x5d, 0x41, 0x5c, 0x5b, 0x5d, 0xc3]);
+
+            let mut exit = |status: u64| {
+                let at = self.code.len();
+                // mov eax, status; jmp epilogue
+                self.emit(&[0xb8]);
+                self.emit_u32(status as u32);
+                self.emit_jump(&[0xe9], Label::Epilogue);
+                at
+            };
+            let out_of_bounds = exit(STATUS_OUT_OF_BOUNDS);
+            let io_error = exit(STATUS_IO_ERROR);
+
+            for (at, label) in std::mem::take(&mut self.fixups) {
+                let target = match label {
+                    Label::Epilogue => epilogue,
+                    Label::OutOfBounds => out_of_bounds,
+                    Label::IoError => io_error,
+                };
+                self.patch(at, target);
+            }
+            self.code
+        }
+    }
+
+    /// State shared with the generated code; it only touches `pointer`, at offset 0.
+    #[repr(C)]
+    struct Context {
+        pointer: usize,
+        input: Box<dyn Read>,
+        output: Box<dyn Write>,
+        // what `run` returns after the code exits with `STATUS_IO_ERROR`
+        error: Option<io::Error>,
+    }
+
+    extern "C" fn output(context: *mut Context, byte: u8) -> u64 {
+        let context = unsafe { &mut *context };
+        match context.output.write_all(&[byte]) {
+            Ok(()) => STATUS_OK,
+            Err(error) => {
+                context.error = Some(error);
+                STATUS_IO_ERROR
+            }
+        }
+    }
+
+    extern "C" fn input(context: *mut Context) -> u64 {
+        let context = unsafe { &mut *context };
+        let mut byte = [0];
+        match context.input.read(&mut byte) {
+            Ok(0) => 0,
+            Ok(_) => byte[0] as u64,
+            Err(error) => {
+                context.error = Some(error);
+                u64::MAX
+            }
+        }
+    }
+
+    type Function = unsafe extern "C" fn(*mut u8, usize, *mut Context) -> u64;
+
+    struct Code {
+        address: *mut libc::c_void,
+        length: usize,
+    }
+
+    impl Code {
+        fn new(bytes: &[u8]) -> io::Result<Self> {
+            let length = bytes.len();
+            let address = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    length,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            if address == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+
+            let code = Self { address, length };
+            unsafe {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, length);
+                if libc::mprotect(address, length, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(code)
+        }
+
+        fn function(&self) -> Function {
+            unsafe { std::mem::transmute::<*mut libc::c_void, Function>(self.address) }
+        }
+    }
+
+    impl Drop for Code {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munmap(self.address, self.length);
+            }
+        }
+    }
+
+    pub struct Jit {
+        code: Code,
+        memory: Box<[u8; MEMORY_LENGTH]>,
+        context: Context,
+        outcome: Outcome,
+    }
+
+    impl Jit {
+        pub fn new(expressions: &[Expression]) -> io::Result<Self> {
+            let bytes = Assembler::new().finish(expressions);
+
+            Ok(Self {
+                code: Code::new(&bytes)?,
+                memory: Box::new([0; MEMORY_LENGTH]),
+                context: Context {
+                    pointer: START_POINTER,
+                    input: Box::new(io::stdin()),
+                    output: Box::new(io::stdout()),
+                    error: None,
+                },
+                outcome: Outcome::default(),
+            })
+        }
+    }
+
+    impl Engine for Jit {
+        fn set_input(&mut self, input: Box<dyn Read>) {
+            self.context.input = input;
+        }
+
+        fn set_output(&mut self, output: Box<dyn Write>) {
+            self.context.output = output;
+        }
+
+        fn run(&mut self) -> io::Result<()> {
+            // the code always starts from the top, so it must not enter a second time
+            if let Some(result) = self.outcome.repeat() {
+                return result;
+            }
+
+            let function = self.code.function();
+            let status = unsafe {
+                function(
+                    self.memory.as_mut_ptr(),
+                    self.context.pointer,
+                    &mut self.context,
+                )
+            };
+
+            let result = match status {
+                STATUS_OUT_OF_BOUNDS => Err(outside_tape()),
+                STATUS_IO_ERROR => Err(self
+                    .context
+                    .error
+                    .take()
+                    .unwrap_or_else(|| io::Error::other("input or output failed"))),
+                _ => self.context.output.flush(),
+            };
+            self.outcome.record(result)
+        }
+
+        fn pointer(&self) -> usize {
+            self.context.pointer
+        }
+
+        fn memory(&self) -> &[u8] {
+            &self.memory[..]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backends::vm::{Interpreter, Vm, jit, testing::run};
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn compare(text: &str, input: &[u8]) {
+        let expressions = Pipeline::execute(text);
+        let vm = Box::new(Vm::from(&Interpreter::interpret(&expressions)));
+
+        assert_eq!(run(jit::compile(&expressions), input), run(vm, input));
+    }
+
+    #[test_case("+++[>+++++<-]>[>++<-]>-<<<+++++.>,[-<+>]<."; "mul val and clear")]
+    #[test_case("+++[>++[>+<--]<-]>>."; "nested loops")]
+    #[test_case(",[.,]"; "echo")]
+    fn matches_vm(text: &str) {
+        compare(text, b"echo me");
+    }
+
+    #[test_case("HelloWorld.bf")]
+    #[test_case("bfeer.bf")]
+    #[test_case("cellsize.bf")]
+    #[test_case("char.bf")]
+    #[test_case("dbff2c.bf")]
+    #[test_case("hanoi.bf")]
+    #[test_case("squares.bf")]
+    #[test_case("triangle.bf")]
+    fn matches_vm_on_programs(program: &str) {
+        let path = format!(
+            "{}/../../data/programs/{program}",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let text = std::fs::read_to_string(path).unwrap();
+
+        compare(&text, b"+[,.]");
+    }
+
+    /// Takes minutes on an unoptimized [`Vm`], so it is left to `cargo test --release`.
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "slow without --release")]
+    fn matches_vm_on_mandelbrot() {
+        matches_vm_on_programs("mandelbrot.bf");
+    }
+
+    #[test_case("+[<+]", 0; "stepping off")]
+    #[test_case(&format!("+{}", "<".repeat(200)), 100; "jumping off")]
+    fn pointer_out_of_bounds(text: &str, pointer: usize) {
+        let mut engine = jit::compile(&Pipeline::execute(text));
+        engine.set_output(Box::new(std::io::sink()));
+
+        let error = engine.run().unwrap_err();
+        assert_eq!(error.to_string(), "pointer moved outside the tape");
+        // the last pointer on the tape, like the vm's
+        assert_eq!(engine.pointer(), pointer);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+
+/// What a single step changed, enough to put the [`Vm`](super::Vm) back where it was.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub index: usize,
+    pub pointer: usize,
+    pub cell: Option<(usize, u8)>,
+    /// Whether the step read a byte, which stepping back gives back to be read again.
+    pub input: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CellWrite {
+    pub step: u64,
+    pub index: usize,
+    pub cell: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Undo entries for the most recent steps, dropping the oldest once `capacity` is reached.
+///
+/// Output is not journaled, so stepping back over a `Print` does not take the byte back.
+/// Input is: stepping back over a `Read` keeps its byte for the next `Read`, so running
+/// forward again reads the same input as the original run.
+#[derive(Debug)]
+pub struct Journal {
+    capacity: usize,
+    steps: u64,
+    entries: VecDeque<Entry>,
+}
+
+impl Journal {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            steps: 0,
+            entries: VecDeque::with_capacity(capacity.min(1 << 16)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of steps executed up to the newest entry.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub(crate) fn record(&mut self, entry: Entry) {
+        self.steps += 1;
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub(crate) fn undo(&mut self) -> Option<Entry> {
+        let entry = self.entries.pop_back()?;
+        self.steps -= 1;
+        Some(entry)
+    }
+
+    pub(crate) fn last_write(&self, cell: usize, memory: &[u8]) -> Option<CellWrite> {
+        let new = *memory.get(cell)?;
+
+        self.entries
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(age, entry)| match entry.cell {
+                Some((address, old)) if address == cell => Some(CellWrite {
+                    step: self.steps - age as u64,
+                    index: entry.index,
+                    cell,
+                    old,
+                    new,
+                }),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::backends::vm::journal::CellWrite;
+    use crate::backends::vm::{Interpreter, Vm, testing::Buffer};
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn vm(text: &str, capacity: usize) -> Vm {
+        let mut vm = Vm::from(&Interpreter::interpret(&Pipeline::execute(text)));
+        vm.enable_journal(capacity);
+        vm
+    }
+
+    #[test_case("+++>++[-<+>]<", 100; "copy loop")]
+    #[test_case("++[>+++<-]>[-]", 100; "clear loop")]
+    #[test_case("++++[>++<--]>>+<<", 3; "bounded history")]
+    fn step_back_restores_state(text: &str, capacity: usize) {
+        let mut vm = vm(text, capacity);
+        let mut states = vec![];
+
+        while !vm.is_halted() {
+            states.push((vm.index(), vm.pointer(), vm.memory().to_vec()));
+            vm.step();
+        }
+
+        for (index, pointer, memory) in states.into_iter().rev().take(capacity) {
+            assert_eq!(vm.step_back(), Some(()));
+            assert_eq!(
+                (vm.index(), vm.pointer(), vm.memory()),
+                (index, pointer, &memory[..])
+            );
+        }
+        assert_eq!(vm.step_back(), None);
+    }
+
+    #[test]
+    fn run_back_to_index() {
+        let mut vm = vm("++++[>+<--]>", 100);
+        vm.run().unwrap();
+
+        assert_eq!(vm.run_back_to(2), Some(()));
+        assert_eq!(vm.index(), 2);
+        assert_eq!(vm.memory()[100..102], [2, 1]);
+        assert_eq!(vm.run_back_to(7), None);
+    }
+
+    #[test]
+    fn step_back_over_read() {
+        let mut vm = vm(",>,<.", 100);
+        let output = Buffer::default();
+        vm.set_input(Cursor::new(b"ab".to_vec()));
+        vm.set_output(output.clone());
+        vm.run().unwrap();
+
+        assert_eq!(vm.run_back_to(0), Some(()));
+        assert_eq!(vm.memory()[100..102], [0, 0]);
+        vm.run().unwrap();
+        assert_eq!(vm.memory()[100..102], *b"ab");
+        assert_eq!(output.bytes(), b"aa");
+    }
+
+    #[test]
+    fn last_write() {
+        let mut vm = vm("++>+++<[-]>+", 100);
+        vm.run().unwrap();
+
+        let write = vm.last_write(100);
+        assert_eq!(
+            write,
+            Some(CellWrite {
+                step: 5,
+                index: 4,
+                cell: 100,
+                old: 2,
+                new: 0
+            })
+        );
+        assert_eq!(vm.last_write(102), None);
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    cmp::Reverse,
+    io::{self, Write},
+};
+
+use crate::{
+    backends::vm::Opcode,
+    core::source_map::{SourceMap, Span},
+};
+
+const SHADES: &[u8] = b" .:-=+*#%@";
+const HEATMAP_WIDTH: usize = 64;
+
+/// Execution counters collected by a [`Vm`](super::Vm) while it runs.
+#[derive(Debug)]
+pub struct Profile {
+    opcodes: Vec<u64>,
+    exits: Vec<u64>,
+    cells: Vec<u64>,
+}
+
+impl Profile {
+    pub fn new(opcodes: usize, cells: usize) -> Self {
+        Self {
+            opcodes: vec![0; opcodes],
+            exits: vec![0; opcodes],
+            cells: vec![0; cells],
+        }
+    }
+
+    pub fn opcodes(&self) -> &[u64] {
+        &self.opcodes
+    }
+
+    pub fn cells(&self) -> &[u64] {
+        &self.cells
+    }
+
+    /// How often the loop between `start` and `end` was entered and how often its body ran.
+    pub fn loop_counts(&self, start: usize, end: usize) -> (u64, u64) {
+        let entries = self.exits[end];
+        let iterations = match start + 1 < end {
+            true => self.opcodes[start + 1],
+            false => self.opcodes[end] - entries,
+        };
+        (entries, iterations)
+    }
+
+    /// Adds the counters of another run of the same opcodes.
+    pub fn merge(&mut self, other: &Profile) {
+        for (lhs, rhs) in [
+            (&mut self.opcodes, &other.opcodes),
+            (&mut self.exits, &other.exits),
+            (&mut self.cells, &other.cells),
+        ] {
+            lhs.iter_mut().zip(rhs).for_each(|(lhs, rhs)| *lhs += rhs);
+        }
+    }
+
+    pub(crate) fn record(&mut self, index: usize, opcode: &Opcode, pointer: usize, value: u8) {
+        self.opcodes[index] += 1;
+
+        let mut access = |cell: Option<usize>| {
+            if let Some(count) = cell.and_then(|cell| self.cells.get_mut(cell)) {
+                *count += 1;
+            }
+        };
+
+        match *opcode {
+            Opcode::IncPtr(_) | Opcode::DecPtr(_) | Opcode::Scan(_) => {}
+            Opcode::MulVal(offset, _) => {
+                access(Some(pointer));
+                access(pointer.checked_add_signed(offset));
+            }
+            Opcode::Copy(offset) => {
+                access(Some(pointer));
+                access(Some(pointer + offset));
+            }
+            Opcode::MovePtrMulVal(..) | Opcode::MovePtrEndLoop(..) => {}
+            Opcode::EndLoop(_) => {
+                access(Some(pointer));
+                if value == 0 {
+                    self.exits[index] += 1;
+                }
+            }
+            _ => access(Some(pointer)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoopProfile {
+    pub start: usize,
+    pub end: usize,
+    pub span: Option<Span>,
+    pub entries: u64,
+    pub iterations: u64,
+    pub executed: u64,
+    pub reason: &'static str,
+}
+
+/// The hottest loops and the tape heatmap of a finished run.
+///
+/// Every loop that still exists in the opcodes is one the optimizer did not replace
+/// with `MulVal`/`Clear`; `reason` says which of its rules the body broke.
+pub struct Report {
+    pub top: usize,
+    pub total: u64,
+    pub opcodes: Vec<u64>,
+    pub loops: Vec<LoopProfile>,
+    pub cells_start: usize,
+    pub cells: Vec<u64>,
+}
+
+impl Report {
+    pub fn new(
+        profile: &Profile,
+        opcodes: &[Opcode],
+        source_map: Option<&SourceMap>,
+        top: usize,
+    ) -> Self {
+        let mut loops = opcodes
+            .iter()
+            .enumerate()
+            .filter_map(|(start, opcode)| match *opcode {
+                Opcode::StartLoop(end) => {
+                    Some(Self::profile_loop(profile, opcodes, source_map, start, end))
+                }
+                _ => None,
+            })
+            .filter(|r#loop| r#loop.executed > 0)
+            .collect::<Vec<_>>();
+        loops.sort_by_key(|r#loop| Reverse(r#loop.executed));
+        loops.truncate(top);
+
+        let touched = profile.cells.iter().position(|count| *count > 0);
+        let (cells_start, cells) = match touched {
+            Some(start) => {
+                let end = profile.cells.iter().rposition(|count| *count > 0).unwrap();
+                (start, profile.cells[start..=end].to_vec())
+            }
+            None => (0, vec![]),
+        };
+
+        Self {
+            top,
+            total: profile.opcodes.iter().sum(),
+            opcodes: profile.opcodes.clone(),
+            loops,
+            cells_start,
+            cells,
+        }
+    }
+
+    fn profile_loop(
+        profile: &Profile,
+        opcodes: &[Opcode],
+        source_map: Option<&SourceMap>,
+        start: usize,
+        end: usize,
+    ) -> LoopProfile {
+        let (entries, iterations) = profile.loop_counts(start, end);
+
+        LoopProfile {
+            start,
+            end,
+            span: source_map
+                .and_then(|map| map.span(start).zip(map.span(end)))
+                .map(|(open, close)| Span {
+                    start: open.start,
+                    end: close.end,
+                }),
+            entries,
+            iterations,
+            executed: profile.opcodes[start..=end].iter().sum(),
+            reason: Self::reason(&opcodes[start + 1..end]),
+        }
+    }
+
+    fn reason(body: &[Opcode]) -> &'static str {
+        let mut offset: isize = 0;
+        let mut counter: i32 = 0;
+
+        for opcode in body {
+            match *opcode {
+                Opcode::StartLoop(_) | Opcode::StartLoopMovePtr(..) | Opcode::Scan(_) => {
+                    return "contains a nested loop";
+                }
+                Opcode::Print | Opcode::Read => return "performs I/O",
+                Opcode::IncPtr(amount) => offset += amount as isize,
+                Opcode::DecPtr(amount) => offset -= amount as isize,
+                Opcode::IncVal(amount) if offset == 0 => counter += amount as i32,
+                Opcode::DecVal(amount) if offset == 0 => counter -= amount as i32,
+                Opcode::Clear
+                | Opcode::MulVal(..)
+                | Opcode::ClearMovePtr(_)
+                | Opcode::MovePtrMulVal(..) => return "contains an optimized inner loop",
+                _ => {}
+            }
+        }
+
+        match (offset, counter) {
+            (0, -1) => "not recognised",
+            (0, _) => "counter does not step by -1",
+            _ => "moves the pointer",
+        }
+    }
+
+    pub fn write_text<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        writeln!(output, "{} opcodes executed", self.total)?;
+
+        let mut hottest = self.opcodes.iter().enumerate().collect::<Vec<_>>();
+        hottest.sort_by_key(|(_, count)| Reverse(**count));
+
+        writeln!(output, "\nhottest opcodes:")?;
+        for (index, count) in hottest.into_iter().take(self.top) {
+            writeln!(
+                output,
+                "{index:0>5} {count:>12} {:>6.2}%",
+                self.share(*count)
+            )?;
+        }
+
+        writeln!(output, "\nhottest loops (not turned into MulVal/Clear):")?;
+        for r#loop in &self.loops {
+            writeln!(
+                output,
+                "{:0>5}..{:0>5} {:>12} {:>6.2}% {:>10} entries {:>12} iterations  {:<16} {}",
+                r#loop.start,
+                r#loop.end,
+                r#loop.executed,
+                self.share(r#loop.executed),
+                r#loop.entries,
+                r#loop.iterations,
+                r#loop.span.map(|span| span.to_string()).unwrap_or_default(),
+                r#loop.reason
+            )?;
+        }
+
+        writeln!(output, "\ntape accesses from cell {}:", self.cells_start)?;
+        let max = self.cells.iter().copied().max().unwrap_or_default();
+        for (row, counts) in self.cells.chunks(HEATMAP_WIDTH).enumerate() {
+            let shades = counts
+                .iter()
+                .map(|count| Self::shade(*count, max) as char)
+                .collect::<String>();
+            writeln!(
+                output,
+                "{:>5} |{shades}|",
+                self.cells_start + row * HEATMAP_WIDTH
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let join = |counts: &[u64]| {
+            counts
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        write!(output, "{{\"total\":{},", self.total)?;
+        write!(output, "\"opcodes\":[{}],", join(&self.opcodes))?;
+
+        write!(output, "\"loops\":[")?;
+        for (number, r#loop) in self.loops.iter().enumerate() {
+            if number > 0 {
+                write!(output, ",")?;
+            }
+            let span = match r#loop.span {
+                Some(span) => format!("\"{span}\""),
+                None => "null".to_string(),
+            };
+            write!(
+                output,
+                "{{\"start\":{},\"end\":{},\"span\":{span},\"entries\":{},\"iterations\":{},\"executed\":{},\"reason\":\"{}\"}}",
+                r#loop.start,
+                r#loop.end,
+                r#loop.entries,
+                r#loop.iterations,
+                r#loop.executed,
+                r#loop.reason
+            )?;
+        }
+        write!(output, "],")?;
+
+        writeln!(
+            output,
+            "\"cells\":{{\"start\":{},\"counts\":[{}]}}}}",
+            self.cells_start,
+            join(&self.cells)
+        )
+    }
+
+    fn share(&self, count: u64) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => count as f64 * 100.0 / total as f64,
+        }
+    }
+
+    fn shade(count: u64, max: u64) -> u8 {
+        match (count, max) {
+            (0, _) => SHADES[0],
+            (_, 1) => SHADES[SHADES.len() - 1],
+            _ => {
+                let level = (count as f64).ln() / (max as f64).ln();
+                SHADES[1 + (level * (SHADES.len() - 2) as f64).round() as usize]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backends::vm::profiler::Report;
+    use crate::backends::vm::{Interpreter, Vm};
+    use crate::core::{pipeline::Pipeline, source_map::SourceMap};
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn report(text: &str) -> Report {
+        let mut vm = Vm::from(&Interpreter::interpret(&Pipeline::execute(text)));
+        vm.enable_profiler();
+        vm.run().unwrap();
+
+        let source_map = SourceMap::new(text);
+        Report::new(
+            vm.profile().unwrap(),
+            &vm.code().decode(),
+            Some(&source_map),
+            10,
+        )
+    }
+
+    #[test_case("++++[>+<--]", "counter does not step by -1")]
+    #[test_case("+>+>+<<[>]", "moves the pointer")]
+    #[test_case("++[>++[>+<--]<-]", "contains a nested loop")]
+    #[test_case("++[>++[>+<-]<-]", "contains an optimized inner loop")]
+    fn loop_reason(text: &str, reason: &str) {
+        assert_eq!(report(text).loops[0].reason, reason);
+    }
+
+    #[test]
+    fn loop_counts() {
+        let report = report("+++[>++++[>+<--]<-]");
+        let inner = &report.loops[1];
+
+        assert_eq!((inner.entries, inner.iterations), (3, 6));
+        assert_eq!(inner.span.unwrap().to_string(), "1:10-1:16");
+        assert_eq!(report.loops[0].iterations, 3);
+    }
+
+    #[test]
+    fn cell_accesses() {
+        let report = report(">>+<+[->+<]");
+
+        assert_eq!(report.cells_start, 101);
+        assert_eq!(report.cells, vec![3, 2]);
+    }
+}
@@ -0,0 +1,207 @@
+use std::io::{self, Read, Write};
+
+use crate::backends::vm::{MEMORY_LENGTH, Opcode, bytecode::Bytecode};
+
+const MAGIC: &[u8; 4] = b"BFVM";
+const VERSION: u32 = 1;
+
+/// The complete state of a [`Vm`](super::Vm), tied to the program it was taken from.
+///
+/// On disk it is the magic `BFVM`, a little-endian `u32` version and then little-endian
+/// `u64`s for the program fingerprint, the opcode index, the pointer, the input position,
+/// the output count and the tape length, followed by the tape itself.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshot {
+    pub fingerprint: u64,
+    pub index: usize,
+    pub pointer: usize,
+    pub input_position: u64,
+    pub output_count: u64,
+    pub memory: Vec<u8>,
+}
+
+impl Snapshot {
+    /// FNV-1a over the opcodes, stable across builds so snapshots survive recompiling.
+    pub fn fingerprint(opcodes: &[Opcode]) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+
+        for opcode in opcodes {
+            let (tag, operands) = Bytecode::operands(*opcode);
+            feed(&[tag]);
+            // each operand in bytes of its own, so a negative one cannot spill into another
+            for operand in operands {
+                feed(&operand.to_le_bytes());
+            }
+        }
+        hash
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        output.write_all(MAGIC)?;
+        output.write_all(&VERSION.to_le_bytes())?;
+
+        for value in [
+            self.fingerprint,
+            self.index as u64,
+            self.pointer as u64,
+            self.input_position,
+            self.output_count,
+            self.memory.len() as u64,
+        ] {
+            output.write_all(&value.to_le_bytes())?;
+        }
+        output.write_all(&self.memory)
+    }
+
+    pub fn read<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a vm snapshot"));
+        }
+
+        let mut version = [0; 4];
+        input.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != VERSION {
+            return Err(invalid_data("unsupported snapshot version"));
+        }
+
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let fingerprint = read_u64()?;
+        let index = read_u64()? as usize;
+        let pointer = read_u64()? as usize;
+        let input_position = read_u64()?;
+        let output_count = read_u64()?;
+        let length = read_u64()?;
+
+        // checked before the tape is allocated, as the file may come from anywhere
+        if length != MEMORY_LENGTH as u64 {
+            return Err(invalid_data("snapshot tape does not fit the vm"));
+        }
+        if pointer >= MEMORY_LENGTH {
+            return Err(invalid_data("snapshot pointer is outside the tape"));
+        }
+        let mut memory = vec![0; MEMORY_LENGTH];
+        input.read_exact(&mut memory)?;
+
+        Ok(Self {
+            fingerprint,
+            index,
+            pointer,
+            input_position,
+            output_count,
+            memory,
+        })
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, sink};
+
+    use crate::backends::vm::{Interpreter, Opcode, Vm, snapshot::Snapshot};
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn vm(text: &str, input: &[u8]) -> Vm {
+        let mut vm = Vm::from(&Interpreter::interpret(&Pipeline::execute(text)));
+        vm.set_input(Cursor::new(input.to_vec()));
+        vm.set_output(sink());
+        vm
+    }
+
+    #[test_case(Opcode::StartLoopMovePtr(1, -1), Opcode::StartLoopMovePtr(2, -1))]
+    #[test_case(Opcode::MovePtrEndLoop(-1, 1), Opcode::MovePtrEndLoop(-1, 2))]
+    #[test_case(Opcode::MovePtrMulVal(-1, 1, 1), Opcode::MovePtrMulVal(-1, 2, 1))]
+    #[test_case(Opcode::MulVal(-1, 1), Opcode::MulVal(-1, 2))]
+    fn fingerprint_operands(left: Opcode, right: Opcode) {
+        assert_ne!(
+            Snapshot::fingerprint(&[left]),
+            Snapshot::fingerprint(&[right])
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut vm = vm(",>,[<+>-]<.", b"\x03\x04");
+        for _ in 0..6 {
+            vm.step();
+        }
+
+        let snapshot = vm.snapshot();
+        let mut bytes = vec![];
+        snapshot.write(&mut bytes).unwrap();
+
+        assert_eq!(Snapshot::read(&mut Cursor::new(bytes)).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn resume() {
+        let text = ",>,[<+>-]<.,";
+        let mut expected = vm(text, b"\x03\x04\x09");
+        expected.run().unwrap();
+
+        let mut first = vm(text, b"\x03\x04\x09");
+        for _ in 0..3 {
+            first.step();
+        }
+
+        let mut resumed = vm(text, b"\x03\x04\x09");
+        resumed.restore(&first.snapshot()).unwrap();
+        resumed.run().unwrap();
+
+        assert_eq!(resumed.snapshot(), expected.snapshot());
+        assert_eq!(resumed.memory()[100], 9);
+    }
+
+    #[test]
+    fn restore_into_other_program() {
+        let snapshot = vm("+>+", b"").snapshot();
+
+        assert!(vm("+>-", b"").restore(&snapshot).is_err());
+    }
+
+    #[test]
+    fn restore_index_outside_program() {
+        let mut snapshot = vm("+>+", b"").snapshot();
+        snapshot.index = 4;
+
+        assert!(vm("+>+", b"").restore(&snapshot).is_err());
+    }
+
+    #[test]
+    fn reject_other_versions() {
+        let mut bytes = vec![];
+        vm("+", b"").snapshot().write(&mut bytes).unwrap();
+        bytes[4] = 2;
+
+        assert!(Snapshot::read(&mut Cursor::new(bytes)).is_err());
+    }
+
+    #[test_case(48, u64::MAX; "huge tape")]
+    #[test_case(48, 10; "short tape")]
+    #[test_case(24, 30_000; "pointer off the tape")]
+    fn reject_invalid_headers(offset: usize, value: u64) {
+        let mut bytes = vec![];
+        vm("+", b"").snapshot().write(&mut bytes).unwrap();
+        bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+
+        let error = Snapshot::read(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::{self, Read, Write};
+
+use crate::backends::vm::{Engine, MEMORY_LENGTH, Opcode, Outcome, START_POINTER, moved};
+
+/// Resolved once at compile time; an error stops the program.
+type Closure = Box<dyn Fn(&mut State) -> io::Result<()>>;
+
+struct State {
+    pointer: usize,
+    memory: Box<[u8; MEMORY_LENGTH]>,
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+}
+
+/// Runs opcodes as a tree of closures: every opcode is matched once while compiling and
+/// every loop owns its body, so no jump targets are looked up while running.
+pub struct Threaded {
+    program: Vec<Closure>,
+    state: State,
+    outcome: Outcome,
+}
+
+impl Threaded {
+    pub fn from(opcodes: &[Opcode]) -> Self {
+        Self {
+            program: Self::compile(opcodes, &Self::closers(opcodes), 0, opcodes.len()),
+            state: State {
+                pointer: START_POINTER,
+                memory: Box::new([0; MEMORY_LENGTH]),
+                input: Box::new(std::io::stdin()),
+                output: Box::new(std::io::stdout()),
+            },
+            outcome: Outcome::default(),
+        }
+    }
+
+    /// Index of the matching closer for every opener, found by nesting since the jump
+    /// targets differ between plain and [`Peephole`](super::peephole::Peephole) opcodes.
+    fn closers(opcodes: &[Opcode]) -> Vec<usize> {
+        let mut closers = vec![0; opcodes.len()];
+        let mut openers = vec![];
+
+        for (index, opcode) in opcodes.iter().enumerate() {
+            match opcode {
+                Opcode::StartLoop(_) | Opcode::StartLoopMovePtr(..) => openers.push(index),
+                Opcode::EndLoop(_) | Opcode::MovePtrEndLoop(..) => {
+                    closers[openers.pop().expect("loops are balanced")] = index
+                }
+                _ => {}
+            }
+        }
+        closers
+    }
+
+    fn move_ptr(offset: isize) -> Closure {
+        Box::new(move |state| {
+            state.pointer = moved(state.pointer, offset)?;
+            Ok(())
+        })
+    }
+
+    fn mul_val(offset: isize, val: u8) -> Closure {
+        Box::new(move |state| {
+            let value = state.memory[state.pointer];
+            // skipped for an empty cell, whose target may lie off the tape
+            if value == 0 {
+                return Ok(());
+            }
+            let value = value.wrapping_mul(val);
+            let cell = &mut state.memory[moved(state.pointer, offset)?];
+            *cell = cell.wrapping_add(value);
+            Ok(())
+        })
+    }
+
+    fn compile(opcodes: &[Opcode], closers: &[usize], start: usize, end: usize) -> Vec<Closure> {
+        let mut closures = vec![];
+        let mut index = start;
+
+        while index < end {
+            let closure: Closure = match opcodes[index] {
+                Opcode::DecVal(amount) => Box::new(move |state| {
+                    let cell = &mut state.memory[state.pointer];
+                    *cell = cell.wrapping_sub(amount);
+                    Ok(())
+                }),
+                Opcode::IncVal(amount) => Box::new(move |state| {
+                    let cell = &mut state.memory[state.pointer];
+                    *cell = cell.wrapping_add(amount);
+                    Ok(())
+                }),
+                Opcode::DecPtr(amount) => Self::move_ptr(-(amount as isize)),
+                Opcode::IncPtr(amount) => Self::move_ptr(amount as isize),
+                Opcode::MulVal(offset, val) => Self::mul_val(offset, val),
+                Opcode::Copy(offset) => Self::mul_val(offset as isize, 1),
+                Opcode::Clear => Box::new(|state| {
+                    state.memory[state.pointer] = 0;
+                    Ok(())
+                }),
+                Opcode::StartLoop(_) | Opcode::StartLoopMovePtr(..) => {
+                    let close = closers[index];
+                    let mut body = Self::compile(opcodes, closers, index + 1, close);
+
+                    if let Opcode::StartLoopMovePtr(_, offset) = opcodes[index] {
+                        body.insert(0, Self::move_ptr(offset));
+                    }
+                    if let Opcode::MovePtrEndLoop(offset, _) = opcodes[close] {
+                        body.push(Self::move_ptr(offset));
+                    }
+                    index = close;
+
+                    Box::new(move |state| {
+                        while state.memory[state.pointer] != 0 {
+                            body.iter().try_for_each(|closure| closure(state))?;
+                        }
+                        Ok(())
+                    })
+                }
+                Opcode::EndLoop(_) | Opcode::MovePtrEndLoop(..) => {
+                    unreachable!("loops are compiled from their start")
+                }
+                Opcode::Scan(stride) => Box::new(move |state| {
+                    while state.memory[state.pointer] != 0 {
+                        state.pointer = moved(state.pointer, stride)?;
+                    }
+                    Ok(())
+                }),
+                Opcode::ClearMovePtr(offset) => Box::new(move |state| {
+                    let pointer = moved(state.pointer, offset)?;
+                    state.memory[state.pointer] = 0;
+                    state.pointer = pointer;
+                    Ok(())
+                }),
+                Opcode::MovePtrMulVal(offset, mul_offset, val) => Box::new(move |state| {
+                    let pointer = moved(state.pointer, offset)?;
+                    let value = state.memory[pointer];
+                    if value != 0 {
+                        let cell = &mut state.memory[moved(pointer, mul_offset)?];
+                        *cell = cell.wrapping_add(value.wrapping_mul(val));
+                    }
+                    state.pointer = pointer;
+                    Ok(())
+                }),
+                Opcode::Print => Box::new(|state| {
+                    let byte = state.memory[state.pointer];
+                    state.output.write_all(&[byte])
+                }),
+                Opcode::Read => Box::new(|state| {
+                    let mut byte = [0];
+                    state.memory[state.pointer] = match state.input.read(&mut byte)? {
+                        0 => 0,
+                        _ => byte[0],
+                    };
+                    Ok(())
+                }),
+            };
+
+            closures.push(closure);
+            index += 1;
+        }
+
+        closures
+    }
+}
+
+impl Engine for Threaded {
+    fn set_input(&mut self, input: Box<dyn Read>) {
+        self.state.input = input;
+    }
+
+    fn set_output(&mut self, output: Box<dyn Write>) {
+        self.state.output = output;
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        if let Some(result) = self.outcome.repeat() {
+            return result;
+        }
+        let result = self
+            .program
+            .iter()
+            .try_for_each(|closure| closure(&mut self.state))
+            .and_then(|()| self.state.output.flush());
+        self.outcome.record(result)
+    }
+
+    fn pointer(&self) -> usize {
+        self.state.pointer
+    }
+
+    fn memory(&self) -> &[u8] {
+        self.state.memory.as_slice()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backends::vm::{
+        Interpreter, Vm, peephole::Peephole, testing::run, threaded::Threaded,
+    };
+    use crate::core::pipeline::Pipeline;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    #[test_case("+++[>+++++<-]>[>++<-]>-<<<+++++.>,[-<+>]<."; "mul val and clear")]
+    #[test_case("+++[>++[>+<--]<-]>>."; "nested loops")]
+    #[test_case(",[.,]"; "echo")]
+    #[test_case("[]+[-]++[[-]]"; "empty and nested clears")]
+    #[test_case("+[>+<<]>>>>++[<]"; "fused moves and scan")]
+    fn matches_vm(text: &str) {
+        let opcodes = Interpreter::interpret(&Pipeline::execute(text));
+        let expected = run(Box::new(Vm::from(&opcodes)), b"echo me");
+
+        assert_eq!(
+            run(Box::new(Threaded::from(&opcodes)), b"echo me"),
+            expected
+        );
+        assert_eq!(
+            run(
+                Box::new(Threaded::from(&Peephole::optimize(&opcodes))),
+                b"echo me"
+            ),
+            expected
+        );
+    }
+}
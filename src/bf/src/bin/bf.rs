@@ -0,0 +1,272 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use bf::{
+    backends::{
+        Backend, CellWidth, Config, Eof, Format, Source, Style, Tape, emitters, executors,
+        transpilers::write_source_map,
+    },
+    core::pipeline::Pipeline,
+};
+
+use clap::{Parser, Subcommand};
+
+/// Runs or emits a program with any backend
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Lists the backends and what they support
+    Backends,
+
+    /// Runs a program with an executing backend
+    Run {
+        #[arg(short, long, default_value = "vm")]
+        backend: String,
+
+        #[arg(short, long)]
+        file: String,
+
+        /// Reads `,` from this file instead of stdin
+        #[arg(short, long)]
+        input: Option<String>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+
+    /// Writes a program as source for another toolchain
+    Emit {
+        #[arg(short, long)]
+        backend: String,
+
+        #[arg(short, long)]
+        file: String,
+
+        /// Defaults to the program file with the backend's extension, `-` for stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Writes a JSON map from lines of plain code to the program here
+        #[arg(long)]
+        source_map: Option<String>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct ConfigArgs {
+    /// Bits per cell: 8, 16 or 32
+    #[arg(long, default_value = "8")]
+    cell_width: CellWidth,
+
+    #[arg(long, default_value_t = 30_000)]
+    tape_size: usize,
+
+    /// What emitted code does when the pointer leaves the tape: unchecked, checked or growing
+    #[arg(long, default_value = "unchecked")]
+    tape: Tape,
+
+    /// What `,` stores at the end of input: zero, unchanged or minus-one
+    #[arg(long, default_value = "zero")]
+    eof: Eof,
+
+    /// How emitted code is written: macros or plain
+    #[arg(long, default_value = "macros")]
+    style: Style,
+
+    /// Comments every block of plain emitted code with its source
+    #[arg(long)]
+    comments: bool,
+
+    /// Points plain emitted C at the program with `#line` directives
+    #[arg(long)]
+    line_directives: bool,
+
+    /// Emits into this file instead of the built-in runtime, see the emitter's placeholders
+    #[arg(long)]
+    runtime_template: Option<PathBuf>,
+
+    /// What the WebAssembly backends write: a wasm module or its wat text
+    #[arg(long = "emit", default_value = "wasm")]
+    format: Format,
+
+    /// Emits Rust as `pub fn NAME(input, output)` to `include!` instead of a program
+    #[arg(long, value_name = "NAME")]
+    function: Option<String>,
+}
+
+impl TryFrom<ConfigArgs> for Config {
+    type Error = std::io::Error;
+
+    fn try_from(args: ConfigArgs) -> std::io::Result<Self> {
+        Ok(Self {
+            cell_width: args.cell_width,
+            tape_size: args.tape_size,
+            tape: args.tape,
+            eof: args.eof,
+            style: args.style,
+            comments: args.comments,
+            line_directives: args.line_directives,
+            runtime: args
+                .runtime_template
+                .map(std::fs::read_to_string)
+                .transpose()?,
+            format: args.format,
+            function: args.function,
+        })
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    match args.command {
+        Command::Backends => {
+            for backend in executors()
+                .iter()
+                .map(|executor| describe(&**executor, "run"))
+            {
+                println!("{backend}");
+            }
+            for backend in emitters()
+                .iter()
+                .map(|emitter| describe(&**emitter, "emit"))
+            {
+                println!("{backend}");
+            }
+            Ok(())
+        }
+        Command::Run {
+            backend,
+            file,
+            input,
+            config,
+        } => {
+            let executor = executors()
+                .into_iter()
+                .find(|executor| executor.name() == backend)
+                .ok_or_else(|| unknown(&backend))?;
+
+            let expressions = Pipeline::execute(&std::fs::read_to_string(file)?);
+            let input: Box<dyn std::io::Read> = match input {
+                Some(input) => Box::new(File::open(input)?),
+                None => Box::new(std::io::stdin()),
+            };
+            executor.execute(
+                &expressions,
+                &config.try_into()?,
+                input,
+                Box::new(std::io::stdout()),
+            )
+        }
+        Command::Emit {
+            backend,
+            file,
+            output,
+            source_map,
+            config,
+        } => {
+            let emitter = emitters()
+                .into_iter()
+                .find(|emitter| emitter.name() == backend)
+                .ok_or_else(|| unknown(&backend))?;
+            let config = Config::try_from(config)?;
+            if config.function.is_some() && emitter.name() != "rust" {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "--function only applies to the rust backend",
+                ));
+            }
+            if source_map.is_some() && config.style != Style::Plain && config.function.is_none() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "source maps need --style plain",
+                ));
+            }
+
+            let text = std::fs::read_to_string(&file)?;
+            let expressions = Pipeline::execute(&text);
+
+            let path = match output {
+                Some(output) => output,
+                None => PathBuf::from(&file)
+                    .with_extension(emitter.extension(&config))
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            let mut output: Box<dyn Write> = match path.as_str() {
+                "-" => Box::new(std::io::stdout().lock()),
+                path => Box::new(BufWriter::new(File::create(path)?)),
+            };
+            let source = Source {
+                path: &file,
+                text: &text,
+            };
+            let mappings = emitter.emit(&expressions, Some(&source), &config, &mut output)?;
+            output.flush()?;
+            #[cfg(unix)]
+            if emitter.executable() && path != "-" {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+            }
+
+            match source_map {
+                Some(source_map) => {
+                    let mut map = BufWriter::new(File::create(source_map)?);
+                    write_source_map(&mut map, &path, &file, &mappings)?;
+                    map.flush()
+                }
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+fn describe(backend: &dyn Backend, command: &str) -> String {
+    let features = backend.features();
+    let join = |items: Vec<String>| items.join(",");
+
+    let tape = match (*features.tape_sizes.start(), *features.tape_sizes.end()) {
+        (start, end) if start == end => format!("{start}"),
+        (start, usize::MAX) => format!("{start}.."),
+        (start, end) => format!("{start}..={end}"),
+    };
+
+    format!(
+        "{:<10} {command:<5} cells {:<8} tape {tape:<8} eof {:<24} {}",
+        backend.name(),
+        join(
+            features
+                .cell_widths
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        ),
+        join(features.eofs.iter().map(ToString::to_string).collect()),
+        join(
+            features
+                .expressions
+                .iter()
+                .map(|feature| format!("{feature:?}"))
+                .collect()
+        ),
+    )
+}
+
+fn unknown(backend: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("unknown backend `{backend}`, see `bf backends`"),
+    )
+}
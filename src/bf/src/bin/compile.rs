@@ -0,0 +1,40 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Read},
+    path::PathBuf,
+};
+
+use bf::{
+    backends::vm::{Interpreter, bytecode::Bytecode},
+    core::pipeline::Pipeline,
+};
+
+use clap::Parser;
+
+/// Compiles a program to `.bfc` bytecode that the vm loads without reparsing the source
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(short, long)]
+    file: String,
+
+    /// Defaults to the program file with the extension replaced by `.bfc`
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    let mut text = String::new();
+    let mut file = File::open(&args.file)?;
+    let _ = file.read_to_string(&mut text)?;
+
+    let opcodes = Interpreter::interpret(&Pipeline::execute(&text));
+    let output = match args.output {
+        Some(output) => PathBuf::from(output),
+        None => PathBuf::from(&args.file).with_extension("bfc"),
+    };
+
+    Bytecode::encode(&opcodes).write(&mut BufWriter::new(File::create(output)?))
+}
@@ -1,12 +1,16 @@
 use std::{
     fs::File,
-    io::{Read, Write},
+    io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
 };
 
 use bf::{
-    backends::transpilers::{
-        Transpiler, c::Transpiler as CTranspiler, rust::Transpiler as RustTranspiler,
+    backends::{
+        Config, Emitter, Source, Style, Tape,
+        transpilers::{
+            c::Transpiler as CTranspiler, elf::Transpiler as ElfTranspiler,
+            rust::Transpiler as RustTranspiler,
+        },
     },
     core::{ir::Expression, pipeline::Pipeline},
 };
@@ -21,46 +25,82 @@ struct Args {
     #[arg(short, long)]
     program_files: Vec<String>,
 
+    /// Directory for the generated files, or `-` to write them all to stdout
     #[arg(short, long)]
     output_directory: String,
+
+    /// How the code is written: macros or plain
+    #[arg(long, default_value = "macros")]
+    style: Style,
+
+    /// What the code does when the pointer leaves the tape: unchecked, checked or growing
+    #[arg(long, default_value = "unchecked")]
+    tape: Tape,
+
+    /// Comments every block of plain code with its source
+    #[arg(long)]
+    comments: bool,
+
+    /// Emits into this file instead of the built-in runtime
+    #[arg(long)]
+    runtime_template: Option<PathBuf>,
 }
 
 #[derive(ValueEnum, Clone, Debug)]
 enum EnumTranspiler {
     C,
     Rust,
+    Elf,
 }
 
 fn main() -> std::io::Result<()> {
     let args: Args = Args::parse();
 
-    let (transpiler, extension): (fn(&[Expression]) -> String, &str) = match args.transpiler {
-        EnumTranspiler::C => (CTranspiler::transpile, "c"),
-        EnumTranspiler::Rust => (RustTranspiler::transpile, "rs"),
+    let transpiler: Box<dyn Emitter> = match args.transpiler {
+        EnumTranspiler::C => Box::new(CTranspiler),
+        EnumTranspiler::Rust => Box::new(RustTranspiler),
+        EnumTranspiler::Elf => Box::new(ElfTranspiler),
+    };
+    let config = Config {
+        style: args.style,
+        comments: args.comments,
+        tape: args.tape,
+        runtime: args
+            .runtime_template
+            .map(std::fs::read_to_string)
+            .transpose()?,
+        ..Config::default()
     };
 
-    let _ = args
-        .program_files
-        .into_iter()
-        .map(|program_file| {
-            let program_file = Path::new(&program_file);
-            let output_program_file_path = {
-                let mut path = PathBuf::from(&args.output_directory);
+    args.program_files.into_iter().try_for_each(|program_file| {
+        let program_file = Path::new(&program_file);
+        let text = read_file_to_string(program_file)?;
+        let expressions = code_to_expressions(&text);
+
+        let mut output: Box<dyn Write> = match args.output_directory.as_str() {
+            "-" => Box::new(std::io::stdout().lock()),
+            output_directory => {
+                let mut path = PathBuf::from(output_directory);
                 path.push(program_file.file_name().unwrap());
-                path.set_extension(extension);
-                path
-            };
-
-            read_file_to_string(Path::new(&program_file))
-                .map(|code| code_to_expressions(&code))
-                .map(|expressions| transpiler(&expressions))
-                .map(|code| write_code_to_file(&code, &output_program_file_path))
-        })
-        .flatten()
-        .flatten()
-        .collect::<Vec<_>>();
-
-    Ok(())
+                path.set_extension(transpiler.extension(&config));
+
+                let file = File::create(path)?;
+                #[cfg(unix)]
+                if transpiler.executable() {
+                    use std::os::unix::fs::PermissionsExt;
+                    file.set_permissions(std::fs::Permissions::from_mode(0o755))?;
+                }
+
+                Box::new(BufWriter::new(file))
+            }
+        };
+        let source = Source {
+            path: &program_file.to_string_lossy(),
+            text: &text,
+        };
+        transpiler.emit(&expressions, Some(&source), &config, &mut output)?;
+        output.flush()
+    })
 }
 
 #[inline(always)]
@@ -75,10 +115,3 @@ fn read_file_to_string(path: &Path) -> std::io::Result<String> {
 fn code_to_expressions(code: &'_ str) -> Vec<Expression> {
     Pipeline::execute(code)
 }
-
-fn write_code_to_file(code: &str, output_file_path: &Path) -> std::io::Result<()> {
-    let mut file = File::create(output_file_path)?;
-    file.write_all(code.as_bytes())?;
-
-    Ok(())
-}
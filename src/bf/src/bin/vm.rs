@@ -1,36 +1,242 @@
-use std::{fs::File, io::Read, time::Instant};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
 
 use bf::{
-    backends::vm::{Interpreter, Vm},
-    core::pipeline::Pipeline,
+    backends::vm::{
+        Engine, Interpreter, Opcode, Vm, bytecode::Bytecode, coverage::Coverage,
+        debugger::Debugger, jit, peephole::Peephole, profiler::Report, snapshot::Snapshot,
+        threaded::Threaded,
+    },
+    core::{ir::Expression, pipeline::Pipeline, source_map::SourceMap},
 };
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     #[arg(short, long)]
     file: String,
+
+    /// Loads `file` as bytecode written by the compile command instead of source
+    #[arg(short, long)]
+    bytecode: bool,
+
+    /// Engine to run the program on; the jit falls back to the vm where it is unsupported
+    #[arg(short, long, value_enum, default_value_t = EnumEngine::Vm)]
+    engine: EnumEngine,
+
+    #[arg(short, long)]
+    debug: bool,
+
+    /// Number of steps the debugger can step back
+    #[arg(long, default_value_t = 1_000_000)]
+    history: usize,
+
+    #[arg(short, long)]
+    profile: bool,
+
+    #[arg(long, value_enum, default_value_t = ProfileFormat::Text)]
+    profile_format: ProfileFormat,
+
+    /// Number of hottest opcodes and loops in the profile
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+
+    /// Runs the program once per input file instead of reading stdin
+    #[arg(short, long)]
+    input: Vec<String>,
+
+    /// Writes lcov coverage aggregated over all runs to this file
+    #[arg(short, long)]
+    coverage: Option<String>,
+
+    /// Saves the vm state to this file when the program ends or is interrupted
+    #[arg(long)]
+    save_on_exit: Option<String>,
+
+    /// Continues from a state saved with --save-on-exit
+    #[arg(long)]
+    resume: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+enum EnumEngine {
+    Vm,
+    Threaded,
+    Jit,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ProfileFormat {
+    Text,
+    Json,
 }
 
 fn main() -> std::io::Result<()> {
     //std::env::set_var("RUST_BACKTRACE", "1");
     let args = Args::parse();
 
-    let mut text = String::new();
-    let mut file = File::open(args.file)?;
-    let _ = file.read_to_string(&mut text)?;
+    let (text, expressions, opcodes) = match args.bytecode {
+        true => {
+            let bytecode = Bytecode::read(&mut BufReader::new(File::open(&args.file)?))?;
+            (None, None, bytecode.decode())
+        }
+        false => {
+            let mut text = String::new();
+            let mut file = File::open(&args.file)?;
+            let _ = file.read_to_string(&mut text)?;
+
+            let expressions = Pipeline::execute(&text);
+            let opcodes = Interpreter::interpret(&expressions);
+            (Some(text), Some(expressions), opcodes)
+        }
+    };
+    let source_map = text.as_deref().map(SourceMap::new);
+
+    if (args.debug || args.coverage.is_some()) && source_map.is_none() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "debugging and coverage need the program source",
+        ));
+    }
+
+    let needs_vm = args.debug
+        || args.profile
+        || args.coverage.is_some()
+        || args.save_on_exit.is_some()
+        || args.resume.is_some();
+    if needs_vm && args.engine != EnumEngine::Vm {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "debugging, profiling, coverage and snapshots need the vm engine",
+        ));
+    }
+
+    if args.save_on_exit.is_some() && args.input.len() > 1 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--save-on-exit saves a single run, so it takes at most one --input",
+        ));
+    }
+
+    // the debugger, profiler, coverage and snapshots all count the unfused opcodes
+    let fused = match needs_vm {
+        true => opcodes.clone(),
+        false => Peephole::optimize(&opcodes),
+    };
+
+    if let (true, Some(text)) = (args.debug, &text) {
+        let mut vm = Vm::from(&opcodes);
+        match args.input.first() {
+            Some(input) => vm.set_input(File::open(input)?),
+            None => vm.set_input(std::io::empty()),
+        }
+        vm.enable_journal(args.history);
+
+        let mut debugger = Debugger::new(vm, SourceMap::new(text), text);
+        return debugger.repl(std::io::stdin().lock(), std::io::stdout());
+    }
+
+    let inputs = match args.input.is_empty() {
+        true => vec![None],
+        false => args.input.iter().map(Some).collect(),
+    };
+    let interrupted = Arc::new(AtomicBool::new(false));
+    if args.save_on_exit.is_some() {
+        let interrupted = interrupted.clone();
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::Relaxed))
+            .map_err(std::io::Error::other)?;
+    }
+
+    let mut coverage = args
+        .coverage
+        .as_ref()
+        .zip(source_map.as_ref())
+        .map(|(_, source_map)| Coverage::new(&args.file, &opcodes, source_map));
 
-    let expressions = Pipeline::execute(&text);
-    let opcodes = Interpreter::interpret(&expressions);
-    let mut vm = Vm::from(&opcodes);
+    for input in inputs {
+        if args.engine != EnumEngine::Vm {
+            let mut engine = compile(&args.engine, expressions.as_deref(), &fused)?;
+            if let Some(input) = input {
+                engine.set_input(Box::new(File::open(input)?));
+            }
 
-    let start = Instant::now();
-    vm.run();
-    let duration = start.elapsed();
+            let start = Instant::now();
+            engine.run()?;
+            println!("{:?}", start.elapsed());
+            continue;
+        }
 
-    println!("{duration:?}");
+        let mut vm = Vm::from(&fused);
+        if let Some(input) = input {
+            vm.set_input(File::open(input)?);
+        }
+        if let Some(path) = &args.resume {
+            vm.restore(&Snapshot::read(&mut File::open(path)?)?)?;
+        }
+        if args.profile || coverage.is_some() {
+            vm.enable_profiler();
+        }
+
+        let start = Instant::now();
+        while !interrupted.load(Ordering::Relaxed) && (0..1 << 16).all(|_| vm.step().is_some()) {}
+        if let Some(error) = vm.take_error() {
+            return Err(error);
+        }
+        let duration = start.elapsed();
+
+        println!("{duration:?}");
+
+        if let Some(path) = &args.save_on_exit {
+            vm.snapshot().write(&mut File::create(path)?)?;
+        }
+
+        if let Some(profile) = vm.profile() {
+            if let Some(coverage) = coverage.as_mut() {
+                coverage.add(profile);
+            }
+
+            if args.profile {
+                let report = Report::new(profile, &fused, source_map.as_ref(), args.top);
+
+                let mut output = std::io::stderr().lock();
+                match args.profile_format {
+                    ProfileFormat::Text => report.write_text(&mut output)?,
+                    ProfileFormat::Json => report.write_json(&mut output)?,
+                }
+            }
+        }
+    }
+
+    if let (Some(path), Some(coverage)) = (&args.coverage, &coverage) {
+        coverage.write_lcov(&mut File::create(path)?)?;
+    }
 
     Ok(())
 }
+
+fn compile(
+    engine: &EnumEngine,
+    expressions: Option<&[Expression]>,
+    opcodes: &[Opcode],
+) -> std::io::Result<Box<dyn Engine>> {
+    Ok(match (engine, expressions) {
+        (EnumEngine::Vm, _) => Box::new(Vm::from(opcodes)),
+        (EnumEngine::Threaded, _) => Box::new(Threaded::from(opcodes)),
+        (EnumEngine::Jit, Some(expressions)) => jit::compile(expressions),
+        (EnumEngine::Jit, None) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the jit compiles from source, not bytecode",
+            ));
+        }
+    })
+}
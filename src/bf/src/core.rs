@@ -2,5 +2,6 @@ pub mod io;
 pub mod ir;
 pub mod parser;
 pub mod pipeline;
+pub mod source_map;
 pub mod token;
 pub mod tokenizer;
@@ -3,7 +3,7 @@ use std::path::Path;
 pub struct Reader;
 
 impl Reader {
-    pub fn read_from_file<'a, T: AsRef<Path>>(_path: &'a str) -> String {
+    pub fn read_from_file<T: AsRef<Path>>(_path: &str) -> String {
         unimplemented!()
     }
 }
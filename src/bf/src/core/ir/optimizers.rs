@@ -137,7 +137,6 @@ impl Optimizer for ConcatOptimizer {
 
 #[derive(Debug, Default)]
 struct CopyOptimizerContext {
-    start_position: usize,
     has_side_effect: bool,
     dec_vals: Vec<u8>,
     inc_vals: Vec<u8>,
@@ -147,9 +146,8 @@ struct CopyOptimizerContext {
 }
 
 impl CopyOptimizerContext {
-    pub fn new(start_position: usize) -> Self {
+    pub fn new() -> Self {
         Self {
-            start_position,
             has_side_effect: false,
             ..Default::default()
         }
@@ -223,8 +221,7 @@ impl Optimizer for CopyOptimizer {
             match expression {
                 Expression::Loop(r#loop) => {
                     let mut loop_optimized = vec![];
-                    let mut context =
-                        CopyOptimizerContext::new(optimized.len().wrapping_sub(1) % usize::MAX);
+                    let mut context = CopyOptimizerContext::new();
                     for expression in r#loop {
                         match expression {
                             Expression::Clear => {
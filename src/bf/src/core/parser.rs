@@ -43,6 +43,21 @@ impl Parser {
         expressions
     }
 
+    /// Returns the index in `tokens` of the first `]` without a `[`, or of the last `[`
+    /// that is never closed, for programs [`Parser::parse`] cannot nest.
+    pub fn unbalanced(tokens: &[Token]) -> Option<usize> {
+        let mut indexes: Vec<usize> = vec![];
+
+        for (index, token) in tokens.iter().enumerate() {
+            match token {
+                Token::OpenBracket => indexes.push(index),
+                Token::CloseBracket if indexes.pop().is_none() => return Some(index),
+                _ => {}
+            }
+        }
+        indexes.pop()
+    }
+
     fn filter(token: &&Token) -> bool {
         !matches!(token, Token::Whitespace(_))
     }
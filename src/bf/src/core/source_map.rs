@@ -0,0 +1,284 @@
+use crate::core::{
+    ir::{Expression, optimizers::Optimizers},
+    token::Token,
+    tokenizer::Tokenizer,
+};
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    fn at(position: Position) -> Self {
+        Self {
+            start: position,
+            end: position,
+        }
+    }
+
+    fn join(self, other: Span) -> Self {
+        Self {
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.start <= position && position <= self.end
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.start == self.end {
+            true => write!(f, "{}", self.start),
+            false => write!(f, "{}-{}", self.start, self.end),
+        }
+    }
+}
+
+enum Node {
+    Command(Expression, Span),
+    Loop(Vec<Node>, Span, Span),
+}
+
+impl Node {
+    fn to_expression(&self) -> Expression {
+        match self {
+            Node::Command(expression, _) => expression.clone(),
+            Node::Loop(nodes, _, _) => {
+                Expression::Loop(nodes.iter().map(Self::to_expression).collect())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Val,
+    Ptr,
+    Io,
+}
+
+impl Class {
+    fn of(expression: &Expression) -> Self {
+        match expression {
+            Expression::IncVal(_) | Expression::DecVal(_) => Class::Val,
+            Expression::IncPtr(_) | Expression::DecPtr(_) => Class::Ptr,
+            _ => Class::Io,
+        }
+    }
+}
+
+/// Maps the optimized program back to the source text it was built from.
+///
+/// There is one span per opcode, laid out the way `Interpreter::interpret` lays out
+/// the expressions of `Pipeline::execute`: every expression gets one entry and a loop
+/// gets one for its `[` before its body and one for its `]` after it. Runs merged by
+/// the optimizer span all of their commands, and loops replaced by `MulVal`/`Clear`
+/// give every replacement the span of the whole loop.
+pub struct SourceMap {
+    spans: Vec<Span>,
+}
+
+impl SourceMap {
+    pub fn new(text: &str) -> Self {
+        let nodes = Self::parse(text);
+        let expressions = nodes.iter().map(Node::to_expression).collect::<Vec<_>>();
+        let optimized = Optimizers::optimize(&expressions);
+
+        let mut spans = vec![];
+        Self::align(&nodes, &optimized, &mut spans);
+
+        Self { spans }
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn span(&self, index: usize) -> Option<Span> {
+        self.spans.get(index).copied()
+    }
+
+    /// Returns the first opcode covering `position`, or the first one after it.
+    pub fn index_of(&self, position: Position) -> Option<usize> {
+        self.spans
+            .iter()
+            .position(|span| span.contains(position))
+            .or_else(|| self.spans.iter().position(|span| span.start > position))
+    }
+
+    fn parse(text: &str) -> Vec<Node> {
+        let mut nodes = vec![];
+        let mut indexes: Vec<(usize, Span)> = vec![];
+        let mut position = Position { line: 1, column: 1 };
+
+        for (char, token) in text.chars().zip(Tokenizer::tokenize(text)) {
+            let span = Span::at(position);
+            match char {
+                '\n' => {
+                    position.line += 1;
+                    position.column = 1;
+                }
+                _ => position.column += 1,
+            }
+
+            let expression = match token {
+                Token::Plus => Expression::IncVal(1),
+                Token::Minus => Expression::DecVal(1),
+                Token::Dot => Expression::Output,
+                Token::Comma => Expression::Input,
+                Token::Shr => Expression::IncPtr(1),
+                Token::Shl => Expression::DecPtr(1),
+                Token::OpenBracket => {
+                    indexes.push((nodes.len(), span));
+                    continue;
+                }
+                Token::CloseBracket => {
+                    if let Some((start_index, open)) = indexes.pop() {
+                        let r#loop = nodes.split_off(start_index);
+                        nodes.push(Node::Loop(r#loop, open, span));
+                    }
+                    continue;
+                }
+                Token::Whitespace(_) => continue,
+            };
+            nodes.push(Node::Command(expression, span));
+        }
+        nodes
+    }
+
+    /// Walks the source nodes of one nesting level alongside their optimized form.
+    ///
+    /// The optimizer only merges neighbouring commands of the same kind (across loops
+    /// it drops as empty) and decides the fate of a loop from its body alone, so each
+    /// loop is optimized on its own to learn whether it survives, is replaced or is
+    /// dropped.
+    fn align(nodes: &[Node], optimized: &[Expression], spans: &mut Vec<Span>) {
+        let mut optimized = optimized.iter();
+        let mut group: Option<(Class, Span)> = None;
+
+        macro_rules! flush {
+            () => {
+                if let Some((_, span)) = group.take() {
+                    spans.push(span);
+                    optimized.next();
+                }
+            };
+        }
+
+        for node in nodes {
+            match node {
+                Node::Command(expression, span) => {
+                    let class = Class::of(expression);
+                    match group {
+                        Some((current, ref mut group_span))
+                            if current == class && class != Class::Io =>
+                        {
+                            *group_span = group_span.join(*span);
+                        }
+                        _ => {
+                            flush!();
+                            group = Some((class, *span));
+                        }
+                    }
+                }
+                Node::Loop(body, open, close) => {
+                    match &Optimizers::optimize(&[node.to_expression()])[..] {
+                        [] => {}
+                        [Expression::Loop(_)] => {
+                            flush!();
+                            if let Some(Expression::Loop(optimized_body)) = optimized.next() {
+                                spans.push(*open);
+                                Self::align(body, optimized_body, spans);
+                                spans.push(*close);
+                            }
+                        }
+                        replaced => {
+                            flush!();
+                            for _ in replaced {
+                                spans.push(open.join(*close));
+                                optimized.next();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        flush!();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backends::vm::Interpreter;
+    use crate::core::pipeline::Pipeline;
+    use crate::core::source_map::{Position, SourceMap};
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    fn position(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test_case("+++", vec![(1, 1, 1, 3)])]
+    #[test_case("+ +\n>>.", vec![(1, 1, 1, 3), (2, 1, 2, 2), (2, 3, 2, 3)])]
+    #[test_case("+[-]>", vec![(1, 1, 1, 1), (1, 2, 1, 4), (1, 5, 1, 5)])]
+    #[test_case("+[->+<]", vec![(1, 1, 1, 1), (1, 2, 1, 7), (1, 2, 1, 7)])]
+    #[test_case("+[>.]", vec![(1, 1, 1, 1), (1, 2, 1, 2), (1, 3, 1, 3), (1, 4, 1, 4), (1, 5, 1, 5)])]
+    #[test_case("+[]+", vec![(1, 1, 1, 4)])]
+    fn spans(text: &str, expected: Vec<(usize, usize, usize, usize)>) {
+        let actual = SourceMap::new(text)
+            .spans()
+            .iter()
+            .map(|span| {
+                (
+                    span.start.line,
+                    span.start.column,
+                    span.end.line,
+                    span.end.column,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+    }
+
+    #[test_case("+[>.]", position(1, 3), Some(2))]
+    #[test_case("+ [>.]", position(1, 2), Some(1))]
+    #[test_case("+[>.]", position(2, 1), None)]
+    fn index_of(text: &str, position: Position, expected: Option<usize>) {
+        assert_eq!(SourceMap::new(text).index_of(position), expected);
+    }
+
+    #[test_case("HelloWorld.bf")]
+    #[test_case("bfeer.bf")]
+    #[test_case("hanoi.bf")]
+    #[test_case("mandelbrot.bf")]
+    #[test_case("squares.bf")]
+    #[test_case("triangle.bf")]
+    fn spans_cover_every_opcode(program: &str) {
+        let path = format!(
+            "{}/../../data/programs/{program}",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let text = std::fs::read_to_string(path).unwrap();
+
+        let opcodes = Interpreter::interpret(&Pipeline::execute(&text));
+        assert_eq!(SourceMap::new(&text).spans().len(), opcodes.len());
+    }
+}
@@ -4,7 +4,7 @@ pub struct Tokenizer;
 
 impl Tokenizer {
     pub fn tokenize(text: &str) -> Vec<Token> {
-        text.chars().into_iter().map(Self::tokenize_char).collect()
+        text.chars().map(Self::tokenize_char).collect()
     }
 
     fn tokenize_char(char: char) -> Token {
@@ -12,6 +12,7 @@ impl Tokenizer {
             '+' => Token::Plus,
             '-' => Token::Minus,
             '.' => Token::Dot,
+            ',' => Token::Comma,
             '>' => Token::Shr,
             '<' => Token::Shl,
             '[' => Token::OpenBracket,
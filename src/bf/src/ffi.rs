@@ -0,0 +1,317 @@
+//! The C ABI of the `cdylib` and `staticlib` builds, declared in `ffi/bf.h`.
+//!
+//! A program is compiled once into an opaque handle and run on the [`Vm`] any number of
+//! times. Failing calls return `NULL` or `-1` and leave a message for [`bf_last_error`].
+
+use std::{
+    cell::RefCell,
+    ffi::{CString, c_char, c_int},
+    io::{self, Cursor, Write},
+    rc::Rc,
+};
+
+use crate::{
+    backends::vm::{Interpreter, Opcode, Vm, peephole::Peephole},
+    core::{ir::optimizers::Optimizers, parser::Parser, tokenizer::Tokenizer},
+};
+
+/// A compiled program, which C only sees behind a pointer.
+pub struct BfProgram {
+    opcodes: Vec<Opcode>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(message: String) {
+    let message = CString::new(message).expect("error messages hold no nul");
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+/// Program output that fails with [`io::ErrorKind::WriteZero`] once `capacity` bytes are
+/// written, which stops the [`Vm`].
+struct Buffer {
+    bytes: Rc<RefCell<Vec<u8>>>,
+    capacity: usize,
+}
+
+impl Write for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let mut buffer = self.bytes.borrow_mut();
+        let count = bytes.len().min(self.capacity - buffer.len());
+        if count == 0 && !bytes.is_empty() {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+
+        buffer.extend_from_slice(&bytes[..count]);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The `length` bytes at `pointer`, where a null pointer holds none.
+///
+/// # Safety
+///
+/// A non-null `pointer` must be valid for reads of `length` bytes.
+unsafe fn bytes<'a>(pointer: *const u8, length: usize) -> Option<&'a [u8]> {
+    match (pointer.is_null(), length) {
+        (true, 0) => Some(&[]),
+        (true, _) => None,
+        // SAFETY: the caller promises `length` readable bytes
+        (false, _) => Some(unsafe { std::slice::from_raw_parts(pointer, length) }),
+    }
+}
+
+/// Compiles the `length` bytes of brainfuck at `text`, or returns `NULL` when they are
+/// not UTF-8 or their brackets do not match.
+///
+/// # Safety
+///
+/// `text` must be valid for reads of `length` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_compile(text: *const c_char, length: usize) -> *mut BfProgram {
+    // SAFETY: the caller promises `length` readable bytes
+    let Some(text) = (unsafe { bytes(text.cast(), length) }) else {
+        fail("bf_compile was given a null program".to_string());
+        return std::ptr::null_mut();
+    };
+    let Ok(text) = std::str::from_utf8(text) else {
+        fail("program is not UTF-8".to_string());
+        return std::ptr::null_mut();
+    };
+
+    let tokens = Tokenizer::tokenize(text);
+    if let Some(index) = Parser::unbalanced(&tokens) {
+        let bracket = text.chars().nth(index).unwrap_or('[');
+        fail(format!(
+            "unmatched `{bracket}` at char {index} of the program"
+        ));
+        return std::ptr::null_mut();
+    }
+    let expressions = Optimizers::optimize(&Parser::parse(&tokens));
+    let opcodes = Peephole::optimize(&Interpreter::interpret(&expressions));
+
+    Box::into_raw(Box::new(BfProgram { opcodes }))
+}
+
+/// Runs `program` on the `input_length` bytes at `input`, with end of input reading as 0,
+/// and writes its output to `output`. Returns 0, or -1 when the output needs more than
+/// `output_capacity` bytes or the pointer leaves the tape. Either way `output_length` is
+/// set to the bytes written.
+///
+/// # Safety
+///
+/// `program` must come from [`bf_compile`] and not be freed, `input` must be valid for
+/// reads of `input_length` bytes, `output` for writes of `output_capacity` bytes and
+/// `output_length` for one write. `input` and `output` may be null when their length is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_run_with_buffers(
+    program: *const BfProgram,
+    input: *const u8,
+    input_length: usize,
+    output: *mut u8,
+    output_capacity: usize,
+    output_length: *mut usize,
+) -> c_int {
+    if program.is_null() || output_length.is_null() || (output.is_null() && output_capacity != 0) {
+        fail("bf_run_with_buffers was given a null pointer".to_string());
+        return -1;
+    }
+    // SAFETY: the caller promises a live program and `input_length` readable bytes
+    let (program, input) = unsafe { (&*program, bytes(input, input_length)) };
+    let Some(input) = input else {
+        fail("bf_run_with_buffers was given null input".to_string());
+        return -1;
+    };
+
+    let written = Rc::new(RefCell::new(vec![]));
+    let mut vm = Vm::from(&program.opcodes);
+    vm.set_input(Cursor::new(input.to_vec()));
+    vm.set_output(Buffer {
+        bytes: written.clone(),
+        capacity: output_capacity,
+    });
+    let result = vm.run();
+
+    let written = written.borrow();
+    if !written.is_empty() {
+        // SAFETY: the caller promises `output_capacity` writable bytes, which `Buffer`
+        // never exceeds
+        unsafe { std::ptr::copy_nonoverlapping(written.as_ptr(), output, written.len()) };
+    }
+    // SAFETY: checked for null above
+    unsafe { *output_length = written.len() };
+
+    match result {
+        Ok(()) => 0,
+        Err(error) if error.kind() == io::ErrorKind::WriteZero => {
+            fail(format!(
+                "output does not fit the buffer of {output_capacity} bytes"
+            ));
+            -1
+        }
+        Err(error) => {
+            fail(error.to_string());
+            -1
+        }
+    }
+}
+
+/// Frees a program from [`bf_compile`]; `NULL` is ignored.
+///
+/// # Safety
+///
+/// `program` must come from [`bf_compile`] and not be freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_free(program: *mut BfProgram) {
+    if !program.is_null() {
+        // SAFETY: the caller hands back ownership of a box from `bf_compile`
+        drop(unsafe { Box::from_raw(program) });
+    }
+}
+
+/// The message of the last call that failed on this thread, or `NULL` before any did.
+/// It lives until the next failure on the thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn bf_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::{path::PathBuf, process::Command};
+
+    use crate::backends::transpilers::toolchain;
+
+    /// Builds `ffi/test.c` against the static library and runs its checks. The library is
+    /// built into a target directory of its own, as the one running the tests is locked
+    /// and may hold an outdated one.
+    #[test]
+    fn c_program() {
+        if !toolchain::available(&["cc"]) {
+            return;
+        }
+        let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let target = std::env::temp_dir().join("bf-ffi-target");
+        let status = Command::new(env!("CARGO"))
+            .args(["build", "--quiet", "--lib", "--manifest-path"])
+            .arg(manifest.join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(&target)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let library = target.join("debug/libbf.a");
+
+        let binary = toolchain::path("ffi", "test");
+        let status = Command::new("cc")
+            .arg("-Wall")
+            .arg("-Werror")
+            .arg("-I")
+            .arg(manifest.join("ffi"))
+            .arg(manifest.join("ffi/test.c"))
+            .arg(&library)
+            .args(["-lpthread", "-ldl", "-lm", "-o"])
+            .arg(&binary)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let output = Command::new(&binary).output().unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(output.status.success(), "{stderr}");
+        // failing runs report through bf_last_error, never by printing
+        assert_eq!(stderr, "");
+    }
+
+    /// The C type of a Rust type in an `extern "C"` signature.
+    fn c_type(rust: &str) -> String {
+        let base = |name: &str| match name {
+            "c_char" => "char".to_string(),
+            "c_int" => "int".to_string(),
+            "u8" => "uint8_t".to_string(),
+            "usize" => "size_t".to_string(),
+            name => name.to_string(),
+        };
+
+        match rust.split_once(' ') {
+            Some(("*const", pointee)) => format!("const {} *", base(pointee)),
+            Some(("*mut", pointee)) => format!("{} *", base(pointee)),
+            _ => base(rust),
+        }
+    }
+
+    /// `type name`, without a space after a pointer's `*`.
+    fn declaration(c_type: &str, name: &str) -> String {
+        match c_type.ends_with('*') {
+            true => format!("{c_type}{name}"),
+            false => format!("{c_type} {name}"),
+        }
+    }
+
+    /// The `extern "C"` functions of this file as the C prototypes they stand for.
+    fn rust_prototypes() -> Vec<String> {
+        include_str!("ffi.rs")
+            .split("extern \"C\" fn ")
+            .skip(1)
+            .map(|function| {
+                let (name, rest) = function.split_once('(').unwrap();
+                let (parameters, rest) = rest.split_once(')').unwrap();
+                let output = match rest.split_once('{').unwrap().0.trim() {
+                    "" => "void".to_string(),
+                    output => c_type(output.trim_start_matches("->").trim()),
+                };
+
+                let parameters = parameters
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|parameter| !parameter.is_empty())
+                    .map(|parameter| {
+                        let (name, rust) = parameter.split_once(": ").unwrap();
+                        declaration(&c_type(rust), name)
+                    })
+                    .collect::<Vec<_>>();
+                let parameters = match parameters.is_empty() {
+                    true => "void".to_string(),
+                    false => parameters.join(", "),
+                };
+
+                format!("{}({parameters})", declaration(&output, name))
+            })
+            .collect()
+    }
+
+    /// The function prototypes of `ffi/bf.h`, each on one line.
+    fn header_prototypes() -> Vec<String> {
+        let mut header = include_str!("../ffi/bf.h").to_string();
+        while let Some(start) = header.find("/*") {
+            let end = start + header[start..].find("*/").unwrap() + 2;
+            header.replace_range(start..end, "");
+        }
+
+        header
+            .split(';')
+            .filter(|declaration| declaration.contains('('))
+            .map(|declaration| {
+                let line = declaration.split_whitespace().collect::<Vec<_>>().join(" ");
+                line.replace("( ", "(").replace(" )", ")")
+            })
+            .collect()
+    }
+
+    /// `ffi/bf.h` is written by hand, so it has to be kept in step with this file.
+    #[test]
+    fn header_matches() {
+        assert_eq!(header_prototypes(), rust_prototypes());
+    }
+}
@@ -1,3 +1,4 @@
 pub mod backends;
 pub mod core;
+pub mod ffi;
 pub mod runtimes;
@@ -0,0 +1,14 @@
+pub fn <NAME>(
+    input: &mut impl std::io::Read,
+    output: &mut impl std::io::Write,
+) -> std::io::Result<()> {
+    #![allow(unused)]
+    use std::io::{Read, Write};
+
+    let mut tape = vec![0 as <CELL>; <MEMORY_LENGTH>];
+    let mut p: usize = 0;
+    let mut input = input.bytes();
+
+<CODE>
+    output.flush()
+}
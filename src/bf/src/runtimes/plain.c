@@ -0,0 +1,17 @@
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef <CELL> cell;
+
+<TAPE>
+
+int main(void) {
+    cell *p = tape;
+    int c;
+
+<CODE>
+    return 0;
+}
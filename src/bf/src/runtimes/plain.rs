@@ -0,0 +1,13 @@
+#![allow(unused)]
+
+use std::io::{Read, Write};
+<TAPE>
+fn main() {
+    let mut tape = vec![0 as <CELL>; <MEMORY_LENGTH>];
+    let mut p: usize = 0;
+    let mut input = std::io::stdin().lock().bytes();
+    let mut output = std::io::stdout().lock();
+
+<CODE>
+    output.flush().unwrap();
+}
@@ -1,12 +1,15 @@
+#include <stdint.h>
 #include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
 
-typedef unsigned char byte;
+typedef <CELL> cell;
 typedef unsigned long long usize;
 
 #define MEMORY memory
-#define MEMORY_LENGTH 30000
-#define MEMORY_DEFINE \
-    byte MEMORY[MEMORY_LENGTH] = { 0 }
+#define MEMORY_LENGTH <MEMORY_LENGTH>
+
+<TAPE>
 
 #define POINTER pointer
 #define POINTER_DEFINE \
@@ -19,16 +22,21 @@ typedef unsigned long long usize;
     MEMORY[POINTER] += amount
 
 #define DEC_PTR_BY(amount) \
-    POINTER -= amount
+    POINTER = shift(POINTER, -(long long) (amount))
 
 #define INC_PTR_BY(amount) \
-    POINTER += amount
+    POINTER = shift(POINTER, amount)
 
 #define CLEAR \
     MEMORY[POINTER] = 0
 
-#define MUL_VAL_BY(offset, amount) \
-    MEMORY[POINTER + offset] += MEMORY[POINTER] * amount
+#define MUL_VAL_BY(offset, amount)                          \
+    do {                                                    \
+        if (MEMORY[POINTER] != 0) {                         \
+            usize target = reach(POINTER, offset) + offset; \
+            MEMORY[target] += MEMORY[POINTER] * amount;     \
+        }                                                   \
+    } while (0)
 
 #define LOOP(expressions)         \
     while(MEMORY[POINTER] != 0) { \
@@ -38,9 +46,14 @@ typedef unsigned long long usize;
 #define OUTPUT \
     printf("%c", MEMORY[POINTER])
 
+#define INPUT                                           \
+    do {                                                \
+        int input = getchar();                          \
+        MEMORY[POINTER] = input == EOF ? <EOF> : input; \
+    } while (0)
+
 int main() {
     POINTER_DEFINE;
-    MEMORY_DEFINE;
 
     <CODE>
 
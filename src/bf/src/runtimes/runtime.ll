@@ -0,0 +1,13 @@
+@tape = internal global [<MEMORY_LENGTH> x <CELL>] zeroinitializer
+
+declare i32 @putchar(i32)
+declare i32 @getchar()
+
+define i32 @main() {
+entry:
+  %pointer = alloca i64
+  store i64 0, ptr %pointer
+
+<CODE>
+  ret i32 0
+}
@@ -0,0 +1,11 @@
+// Runs the program on `input`, the bytes `,` reads, and returns the bytes `.` wrote.
+export function run(input = new Uint8Array()) {
+    const tape = new <CELL>(<MEMORY_LENGTH>);
+    const output = [];
+    let p = <START>;
+    let i = 0;
+
+<CODE>
+    return Uint8Array.from(output);
+}
+<CLI>
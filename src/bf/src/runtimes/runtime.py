@@ -0,0 +1,15 @@
+import sys
+
+
+def main():
+    tape = <TAPE>
+    p = 0
+    stdin = sys.stdin.buffer
+    stdout = sys.stdout.buffer
+
+<CODE>
+    stdout.flush()
+
+
+if __name__ == "__main__":
+    main()
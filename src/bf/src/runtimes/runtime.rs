@@ -12,21 +12,24 @@ macro_rules! dec_val_by {
 
 macro_rules! mul_val_by {
     ($memory:expr, $index:expr, $offset:expr, $amount:expr) => {
-        let offset = $index.checked_add_signed($offset).unwrap();
+        if $memory[$index] != 0 {
+            reach(&mut $memory, $index, $offset);
+            let offset = $index.wrapping_add_signed($offset);
 
-        $memory[offset] = $memory[offset].wrapping_add($memory[$index].wrapping_mul($amount));
+            $memory[offset] = $memory[offset].wrapping_add($memory[$index].wrapping_mul($amount));
+        }
     };
 }
 
 macro_rules! inc_ptr_by {
-    ($pointer:expr, $amount:expr) => {
-        $pointer += $amount
+    ($memory:expr, $pointer:expr, $amount:expr) => {
+        $pointer = shift(&mut $memory, $pointer, $amount as isize)
     };
 }
 
 macro_rules! dec_ptr_by {
-    ($pointer:expr, $amount:expr) => {
-        $pointer -= $amount
+    ($memory:expr, $pointer:expr, $amount:expr) => {
+        $pointer = shift(&mut $memory, $pointer, -($amount as isize))
     };
 }
 
@@ -48,13 +51,25 @@ macro_rules! clear {
 
 macro_rules! output {
     ($memory:expr, $pointer:expr) => {
-        print!("{}", $memory[$pointer] as char);
+        std::io::Write::write_all(&mut std::io::stdout(), &[$memory[$pointer] as u8]).unwrap();
     };
 }
 
+macro_rules! input {
+    ($memory:expr, $pointer:expr) => {{
+        let mut byte = [0u8];
+        $memory[$pointer] = match std::io::Read::read(&mut std::io::stdin(), &mut byte) {
+            Ok(1) => byte[0] as _,
+            _ => <EOF>,
+        };
+    }};
+}
+
+<TAPE>
+
 fn main() {
     let mut <POINTER> = 0 as usize;
-    let mut <MEMORY> = [0 as u8; 30_000];
+    let mut <MEMORY> = vec![0 as <CELL>; <MEMORY_LENGTH>];
 
 <CODE>
 }
\ No newline at end of file
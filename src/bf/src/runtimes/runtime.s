@@ -0,0 +1,64 @@
+# <MEMORY_LENGTH> cells of <CELL_SIZE> bytes, the pointer in %rbx and the count of buffered
+# output in %r12; links without libc: as -o program.o program.s && ld -o program program.o
+
+    .bss
+    .align 64
+tape:
+    .zero <MEMORY_LENGTH> * <CELL_SIZE>
+buffer:
+    .zero 4096
+input:
+    .zero 1
+
+    .text
+    .globl _start
+_start:
+    lea tape(%rip), %rbx
+    xor %r12d, %r12d
+
+<CODE>
+    call flush
+    mov $60, %eax
+    xor %edi, %edi
+    syscall
+
+# writes the buffered output to stdout
+flush:
+    lea buffer(%rip), %rsi
+    mov %r12, %rdx
+1:  test %rdx, %rdx
+    jz 2f
+    mov $1, %eax
+    mov $1, %edi
+    syscall
+    test %rax, %rax
+    jle 2f
+    add %rax, %rsi
+    sub %rax, %rdx
+    jmp 1b
+2:  xor %r12d, %r12d
+    ret
+
+# buffers the byte in %al
+putchar:
+    lea buffer(%rip), %rdx
+    mov %al, (%rdx,%r12)
+    inc %r12
+    cmp $4096, %r12
+    je flush
+    ret
+
+# flushes the output, then reads a byte from stdin into %eax, or -1 at the end of input
+getchar:
+    call flush
+    xor %eax, %eax
+    xor %edi, %edi
+    lea input(%rip), %rsi
+    mov $1, %edx
+    syscall
+    test %rax, %rax
+    jle 1f
+    movzbl input(%rip), %eax
+    ret
+1:  mov $-1, %eax
+    ret
@@ -0,0 +1,16 @@
+static cell MEMORY[MEMORY_LENGTH];
+
+static inline usize reach(usize pointer, long long offset) {
+    long long index = (long long) pointer + offset;
+
+    if (index < 0 || index >= (long long) MEMORY_LENGTH) {
+        fprintf(stderr, "error: pointer moved to cell %lld, outside the tape of %llu cells\n",
+                index, (usize) MEMORY_LENGTH);
+        exit(1);
+    }
+    return pointer;
+}
+
+static inline usize shift(usize pointer, long long offset) {
+    return reach(pointer, offset) + offset;
+}
@@ -0,0 +1,18 @@
+fn reach(memory: &mut Vec<<CELL>>, pointer: usize, offset: isize) {
+    match pointer.checked_add_signed(offset) {
+        Some(index) if index < memory.len() => {}
+        _ => {
+            eprintln!(
+                "error: pointer moved to cell {}, outside the tape of {} cells",
+                pointer as isize + offset,
+                memory.len()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+fn shift(memory: &mut Vec<<CELL>>, pointer: usize, offset: isize) -> usize {
+    reach(memory, pointer, offset);
+    pointer.wrapping_add_signed(offset)
+}
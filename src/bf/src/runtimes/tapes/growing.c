@@ -0,0 +1,37 @@
+static cell initial[MEMORY_LENGTH];
+static cell *MEMORY = initial;
+static usize length = MEMORY_LENGTH;
+
+static inline usize reach(usize pointer, long long offset) {
+    long long index = (long long) pointer + offset;
+    usize grown = length;
+    cell *tape;
+
+    if (index < 0) {
+        fprintf(stderr, "error: pointer moved to cell %lld, left of the tape\n", index);
+        exit(1);
+    }
+    if ((usize) index < length) {
+        return pointer;
+    }
+
+    while (grown <= (usize) index) {
+        grown *= 2;
+    }
+    tape = MEMORY == initial ? malloc(grown * sizeof(cell)) : realloc(MEMORY, grown * sizeof(cell));
+    if (tape == NULL) {
+        fprintf(stderr, "error: no memory for a tape of %llu cells\n", grown);
+        exit(1);
+    }
+    if (MEMORY == initial) {
+        memcpy(tape, initial, sizeof(initial));
+    }
+    memset(tape + length, 0, (grown - length) * sizeof(cell));
+    MEMORY = tape;
+    length = grown;
+    return pointer;
+}
+
+static inline usize shift(usize pointer, long long offset) {
+    return reach(pointer, offset) + offset;
+}
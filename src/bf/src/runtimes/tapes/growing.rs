@@ -0,0 +1,18 @@
+fn reach(memory: &mut Vec<<CELL>>, pointer: usize, offset: isize) {
+    match pointer.checked_add_signed(offset) {
+        Some(index) if index < memory.len() => {}
+        Some(index) => memory.resize((index + 1).max(memory.len() * 2), 0),
+        None => {
+            eprintln!(
+                "error: pointer moved to cell {}, left of the tape",
+                pointer as isize + offset
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+fn shift(memory: &mut Vec<<CELL>>, pointer: usize, offset: isize) -> usize {
+    reach(memory, pointer, offset);
+    pointer.wrapping_add_signed(offset)
+}
@@ -0,0 +1,16 @@
+static cell tape[<MEMORY_LENGTH>];
+
+static cell *reach(cell *p, ptrdiff_t offset) {
+    ptrdiff_t index = p - tape + offset;
+
+    if (index < 0 || index >= <MEMORY_LENGTH>) {
+        fprintf(stderr, "error: pointer moved to cell %td, outside the tape of <MEMORY_LENGTH> cells\n",
+                index);
+        exit(1);
+    }
+    return p;
+}
+
+static cell *shift(cell *p, ptrdiff_t offset) {
+    return reach(p, offset) + offset;
+}
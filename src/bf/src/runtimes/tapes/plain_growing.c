@@ -0,0 +1,37 @@
+static cell initial[<MEMORY_LENGTH>];
+static cell *tape = initial;
+static size_t length = <MEMORY_LENGTH>;
+
+static cell *reach(cell *p, ptrdiff_t offset) {
+    ptrdiff_t at = p - tape;
+    size_t grown = length;
+    cell *moved;
+
+    if (at + offset < 0) {
+        fprintf(stderr, "error: pointer moved to cell %td, left of the tape\n", at + offset);
+        exit(1);
+    }
+    if ((size_t) (at + offset) < length) {
+        return p;
+    }
+
+    while (grown <= (size_t) (at + offset)) {
+        grown *= 2;
+    }
+    moved = tape == initial ? malloc(grown * sizeof(cell)) : realloc(tape, grown * sizeof(cell));
+    if (moved == NULL) {
+        fprintf(stderr, "error: no memory for a tape of %zu cells\n", grown);
+        exit(1);
+    }
+    if (tape == initial) {
+        memcpy(moved, initial, sizeof(initial));
+    }
+    memset(moved + length, 0, (grown - length) * sizeof(cell));
+    tape = moved;
+    length = grown;
+    return tape + at;
+}
+
+static cell *shift(cell *p, ptrdiff_t offset) {
+    return reach(p, offset) + offset;
+}
@@ -0,0 +1 @@
+static cell tape[<MEMORY_LENGTH>];
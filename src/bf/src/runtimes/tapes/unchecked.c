@@ -0,0 +1,10 @@
+static cell MEMORY[MEMORY_LENGTH];
+
+static inline usize reach(usize pointer, long long offset) {
+    (void) offset;
+    return pointer;
+}
+
+static inline usize shift(usize pointer, long long offset) {
+    return pointer + offset;
+}
@@ -0,0 +1,5 @@
+fn reach(memory: &mut Vec<<CELL>>, pointer: usize, offset: isize) {}
+
+fn shift(memory: &mut Vec<<CELL>>, pointer: usize, offset: isize) -> usize {
+    pointer.wrapping_add_signed(offset)
+}